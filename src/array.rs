@@ -0,0 +1,174 @@
+//! # Multiple expanders
+//!
+//! [ExpanderArray] owns up to four PCA9539 (addresses 0x74–0x77) and numbers their pins globally. Each expander is
+//! wrapped in an [ExpanderLock] of choice, see [locking backends](crate::lock).
+//! Global index `n` addresses device `n / 16`, bank `(n % 16) / 8` and pin `n % 8`. So pins 0..15 belong to the
+//! first device, pins 16..31 to the second device and so on.
+//!
+//! As each expander owns its bus, a shared bus implementation (e.g. `embassy-embedded-hal`) is used for
+//! devices connected to the same I2C bus.
+//! ```
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::array::ExpanderArray;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use embedded_hal::digital::InputPin;
+//!#
+//!# embassy_futures::block_on(async {
+//! let expanders = ExpanderArray::new([
+//!     Mutex::<NoopRawMutex, _>::new(PCA9539::new(DummyI2CBus::default(), 0x74, DummyResetPin::default()).await),
+//!     Mutex::<NoopRawMutex, _>::new(PCA9539::new(DummyI2CBus::default(), 0x75, DummyResetPin::default()).await),
+//! ]);
+//!
+//! // Pin1 of Bank0 of the second device
+//! let mut pin = expanders.get_refreshable_pin(17).unwrap();
+//!
+//! expanders.refresh_all().await.unwrap();
+//! assert!(pin.is_high().unwrap());
+//!# });
+//! ```
+
+use crate::expander::Bank::{Bank0, Bank1};
+use crate::expander::{Bank, PinID, RefreshInputError, PIN_IDS};
+use crate::lock::ExpanderLock;
+use crate::pins::{DynLocation, Input, Pin, RefreshMode, RegularAccessMode, TakenMask};
+use crate::wait::NoWait;
+use core::fmt::{Debug, Formatter};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+
+/// Number of pins per device
+pub const PINS_PER_DEVICE: usize = 16;
+
+/// Input pin handed out by [ExpanderArray]
+pub type ArrayPin<'a, I2CT, RESET, LOCK, ACCESS> = Pin<'a, I2CT, RESET, LOCK, Input, ACCESS>;
+
+/// Error of an individual device
+pub struct DeviceError<E> {
+    /// Index of the failed device
+    pub device: usize,
+    pub error: E,
+}
+
+impl<E: Debug> Debug for DeviceError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "DeviceError {{ device: {}, error: {:?} }}", self.device, self.error)
+    }
+}
+
+/// Owns multiple expanders and addresses their pins by global index
+pub struct ExpanderArray<I2CT, RESET, LOCK, const DEVICES: usize>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    devices: [LOCK; DEVICES],
+
+    /// Pins handed out per device
+    taken: [TakenMask<LOCK::RawMutex>; DEVICES],
+}
+
+impl<I2CT, RESET, LOCK, const DEVICES: usize> ExpanderArray<I2CT, RESET, LOCK, DEVICES>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    /// Takes over the given expanders, array position defines the device index
+    pub fn new(expanders: [LOCK; DEVICES]) -> Self {
+        Self {
+            devices: expanders,
+            taken: core::array::from_fn(|_| TakenMask::new()),
+        }
+    }
+
+    /// Returns the total number of pins
+    pub const fn pin_count(&self) -> usize {
+        DEVICES * PINS_PER_DEVICE
+    }
+
+    /// Translates a global pin index into device index, bank and pin ID
+    /// Returns None if the index is out of range.
+    pub fn locate(&self, index: usize) -> Option<(usize, Bank, PinID)> {
+        if index >= self.pin_count() {
+            return None;
+        }
+
+        let bank = match (index % PINS_PER_DEVICE) / 8 {
+            0 => Bank0,
+            _ => Bank1,
+        };
+
+        Some((index / PINS_PER_DEVICE, bank, PIN_IDS[index % 8]))
+    }
+
+    /// Returns the shared expander of the given device index
+    /// Pins fetched directly from the expander are not tracked by this array.
+    pub fn device(&self, device: usize) -> Option<&LOCK> {
+        self.devices.get(device)
+    }
+
+    /// Returns the pin of the given global index, which state gets updated synchronously
+    /// Returns None if the index is out of range or the pin is already in use.
+    pub fn get_pin(&self, index: usize) -> Option<ArrayPin<'_, I2CT, RESET, LOCK, RegularAccessMode>> {
+        let (device, bank, id) = self.locate(index)?;
+        Pin::claim(
+            &self.devices[device],
+            &NoWait {},
+            &self.taken[device],
+            DynLocation { bank, id },
+        )
+    }
+
+    /// Returns the pin of the given global index, which is using a cached state
+    /// Returns None if the index is out of range or the pin is already in use.
+    pub fn get_refreshable_pin(&self, index: usize) -> Option<ArrayPin<'_, I2CT, RESET, LOCK, RefreshMode>> {
+        let (device, bank, id) = self.locate(index)?;
+        Pin::claim(
+            &self.devices[device],
+            &NoWait {},
+            &self.taken[device],
+            DynLocation { bank, id },
+        )
+    }
+
+    /// Refreshes the input state of both banks of all devices
+    /// Stops at the first failed device.
+    pub async fn refresh_all(&self) -> Result<(), DeviceError<RefreshInputError<I2CT>>> {
+        for (index, device) in self.devices.iter().enumerate() {
+            let mut expander = device.lock_timeout().await.map_err(|timeout| DeviceError {
+                device: index,
+                error: timeout.into(),
+            })?;
+
+            for bank in [Bank0, Bank1] {
+                expander
+                    .refresh_input_state(bank)
+                    .await
+                    .map_err(|error| DeviceError { device: index, error })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the cached output, polarity and configuration registers of all devices, unchanged registers are
+    /// skipped. Stops at the first failed device.
+    pub async fn flush_all(&self) -> Result<(), DeviceError<RefreshInputError<I2CT>>> {
+        for (index, device) in self.devices.iter().enumerate() {
+            let mut expander = device.lock_timeout().await.map_err(|timeout| DeviceError {
+                device: index,
+                error: timeout.into(),
+            })?;
+
+            expander.flush().await.map_err(|error| DeviceError {
+                device: index,
+                error: expander.bus_error(error),
+            })?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! # Software debouncing
+//!
+//! [DebouncedInput] wraps an input pin and just reports levels, which are stable according to the
+//! configured [DebouncePolicy]. The debounced state is updated by calling [DebouncedInput::update()],
+//! usually after refreshing the input state of [refresh mode pins](crate::pins::RefreshMode).
+//! ```
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!# use pca9539::debounce::{DebouncedInput, DebouncePolicy, Edge};
+//!# use pca9539::pins::{Pins, RefreshableInputPin};
+//!# use embedded_hal::digital::InputPin;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let pin01 = pins.get_refreshable_pin(Bank0, Pin1).unwrap();
+//! pin01.refresh_bank().await.unwrap();
+//!
+//! // Level is accepted after three consecutive equal samples
+//! let mut switch = DebouncedInput::new(pin01, DebouncePolicy::Samples(3));
+//! assert_eq!(None, switch.update(0).unwrap());
+//! assert!(switch.is_high().unwrap());
+//!# });
+//! ```
+
+use core::convert::Infallible;
+use embedded_hal::digital::{ErrorType, InputPin};
+
+/// Defines when a changed input level is accepted
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DebouncePolicy {
+    /// Level is accepted after the given number of consecutive equal samples
+    Samples(u8),
+    /// Level is accepted after being stable for the given time window in microseconds
+    Window(u32),
+}
+
+/// Debounced edge
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// Input pin just reporting debounced levels
+pub struct DebouncedInput<P: InputPin> {
+    pin: P,
+    policy: DebouncePolicy,
+
+    /// Debounced level, None until the first sample
+    state: Option<bool>,
+
+    /// Level of the last sample
+    candidate: bool,
+
+    /// Number of consecutive samples matching the candidate level
+    count: u8,
+
+    /// Timestamp in microseconds, when the candidate level was sampled first
+    since_us: u64,
+}
+
+impl<P: InputPin> DebouncedInput<P> {
+    pub fn new(pin: P, policy: DebouncePolicy) -> Self {
+        Self {
+            pin,
+            policy,
+            state: None,
+            candidate: false,
+            count: 0,
+            since_us: 0,
+        }
+    }
+
+    /// Samples the wrapped pin and updates the debounced level
+    /// The first sample is taken over directly. Returns the debounced edge, if the level has changed.
+    /// `now_us` is a monotonic timestamp in microseconds, which is just used by [DebouncePolicy::Window].
+    pub fn update(&mut self, now_us: u64) -> Result<Option<Edge>, P::Error> {
+        let sample = self.pin.is_high()?;
+
+        if sample != self.candidate || self.state.is_none() {
+            self.candidate = sample;
+            self.count = 1;
+            self.since_us = now_us;
+        } else {
+            self.count = self.count.saturating_add(1);
+        }
+
+        let state = match self.state {
+            Some(state) => state,
+            None => {
+                self.state = Some(sample);
+                return Ok(None);
+            }
+        };
+
+        if self.candidate == state || !self.is_stable(now_us) {
+            return Ok(None);
+        }
+
+        self.state = Some(self.candidate);
+        Ok(Some(match self.candidate {
+            true => Edge::Rising,
+            false => Edge::Falling,
+        }))
+    }
+
+    /// Returns the wrapped pin
+    pub fn release(self) -> P {
+        self.pin
+    }
+
+    /// Returns true if the candidate level is stable according to the policy
+    fn is_stable(&self, now_us: u64) -> bool {
+        match self.policy {
+            DebouncePolicy::Samples(samples) => self.count >= samples,
+            DebouncePolicy::Window(window_us) => now_us.saturating_sub(self.since_us) >= window_us as u64,
+        }
+    }
+}
+
+impl<P: InputPin> ErrorType for DebouncedInput<P> {
+    type Error = Infallible;
+}
+
+/// As this is just acting on the debounced state, its in fact Infallible
+/// Pins are reported low until the first update.
+impl<P: InputPin> InputPin for DebouncedInput<P> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.state == Some(true))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
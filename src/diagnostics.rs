@@ -0,0 +1,144 @@
+//! # Diagnostics
+//!
+//! Each expander counts its bus transactions, errors and retries, e.g. for reporting the I/O health via telemetry.
+//! The counters are read from the expander directly or through [Pins](crate::pins::Pins). All counters wrap on
+//! overflow.
+//! ```
+//!# use core::cell::RefCell;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::pins::Pins;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! let mut expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//! expander.refresh_input_state(Bank0).await.unwrap();
+//! assert_eq!(1, expander.diagnostics().reads);
+//!
+//! let expander = RefCell::new(expander);
+//! let pins = Pins::new(&expander);
+//!
+//! let diagnostics = pins.diagnostics().unwrap();
+//! assert_eq!(0, diagnostics.errors());
+//! assert!(diagnostics.last_error.is_none());
+//!# });
+//! ```
+
+use embedded_hal::i2c::ErrorKind;
+
+/// Register of the device
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Register {
+    Input0 = 0,
+    Input1 = 1,
+    Output0 = 2,
+    Output1 = 3,
+    Polarity0 = 4,
+    Polarity1 = 5,
+    Configuration0 = 6,
+    Configuration1 = 7,
+}
+
+/// Registers ordered by command
+const REGISTERS: [Register; 8] = [
+    Register::Input0,
+    Register::Input1,
+    Register::Output0,
+    Register::Output1,
+    Register::Polarity0,
+    Register::Polarity1,
+    Register::Configuration0,
+    Register::Configuration1,
+];
+
+/// Most recent bus error
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LastError {
+    /// Register of the failed transaction
+    pub register: Register,
+
+    /// Kind of the bus error
+    pub kind: ErrorKind,
+
+    /// Sequence number of the failed transaction, counting all reads and writes starting at 1
+    pub sequence: u32,
+}
+
+/// Transaction, error and retry counters of an expander
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Diagnostics {
+    /// Number of read transactions, including retries
+    pub reads: u32,
+
+    /// Number of write transactions, including retries
+    pub writes: u32,
+
+    /// Number of retried transactions
+    pub retries: u32,
+
+    /// Number of failed transactions per register, indexed by register
+    register_errors: [u32; 8],
+
+    /// Number of failed transactions per error kind, indexed by `kind_index()`
+    kind_errors: [u32; 5],
+
+    /// Most recent error, if any
+    pub last_error: Option<LastError>,
+}
+
+impl Diagnostics {
+    /// Returns the total number of failed transactions
+    pub fn errors(&self) -> u32 {
+        self.register_errors.iter().fold(0, |sum, count| sum.wrapping_add(*count))
+    }
+
+    /// Returns the number of failed transactions of the given register
+    pub fn register_errors(&self, register: Register) -> u32 {
+        self.register_errors[register as usize]
+    }
+
+    /// Returns the number of failed transactions of the given error kind
+    /// NACKs are counted regardless of their source, unknown kinds are counted as [ErrorKind::Other].
+    pub fn kind_errors(&self, kind: ErrorKind) -> u32 {
+        self.kind_errors[Self::kind_index(kind)]
+    }
+
+    /// Counts a read or write transaction
+    pub(crate) fn record_transaction(&mut self, is_read: bool) {
+        if is_read {
+            self.reads = self.reads.wrapping_add(1);
+        } else {
+            self.writes = self.writes.wrapping_add(1);
+        }
+    }
+
+    /// Counts a failed transaction of the given command
+    pub(crate) fn record_error(&mut self, command: u8, kind: ErrorKind) {
+        let register = REGISTERS[command as usize & 0x07];
+
+        self.register_errors[register as usize] = self.register_errors[register as usize].wrapping_add(1);
+        self.kind_errors[Self::kind_index(kind)] = self.kind_errors[Self::kind_index(kind)].wrapping_add(1);
+        self.last_error = Some(LastError {
+            register,
+            kind,
+            sequence: self.reads.wrapping_add(self.writes),
+        });
+    }
+
+    /// Counts a retry
+    pub(crate) fn record_retry(&mut self) {
+        self.retries = self.retries.wrapping_add(1);
+    }
+
+    /// Returns the counter index of the given error kind
+    fn kind_index(kind: ErrorKind) -> usize {
+        match kind {
+            ErrorKind::Bus => 0,
+            ErrorKind::ArbitrationLoss => 1,
+            ErrorKind::NoAcknowledge(_) => 2,
+            ErrorKind::Overrun => 3,
+            _ => 4,
+        }
+    }
+}
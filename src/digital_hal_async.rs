@@ -1,24 +1,117 @@
-use core::convert::Infallible;
-use embedded_hal::digital::{OutputPin, InputPin, PinState};
+//! # Async digital traits
+//!
+//! Async counterparts of the [digital traits of embedded_hal](embedded_hal::digital), implemented by
+//! all expander pins. Drivers generic over [InputPinAsync] or [OutputPinAsync] accept both, expander pins
+//! and any native GPIO wrapped by [AsyncAdapter].
+//! ```
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin4;
+//!# use pca9539::pins::Pins;
+//! use pca9539::digital_hal_async::{AsyncAdapter, OutputPinAsync};
+//!# use embedded_hal::digital::PinState;
+//!
+//! /// Driver, which is written once for any async output pin
+//! async fn blink<P: OutputPinAsync>(pin: &mut P) -> Result<(), P::Error> {
+//!     pin.set_high_async().await?;
+//!     pin.set_low_async().await
+//! }
+//!
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let mut expander_pin = pins.get_pin(Bank0, Pin4).unwrap().into_output_pin(PinState::Low).await.unwrap();
+//! blink(&mut expander_pin).await.unwrap();
+//!
+//! // Native GPIO of the MCU
+//! let mut native_pin = AsyncAdapter::new(DummyResetPin::default());
+//! blink(&mut native_pin).await.unwrap();
+//!# });
+//! ```
+use embedded_hal::digital::{InputPin, OutputPin, PinState};
 
-pub trait Error : core::fmt::Debug  {}
+/// Error of async pins, implemented for all [embedded_hal errors](embedded_hal::digital::Error)
+pub trait Error: embedded_hal::digital::Error {}
 
-impl Error for Infallible {}
+impl<E: embedded_hal::digital::Error> Error for E {}
 
+/// Defines the error type of async pins
 pub trait ErrorType {
-    type Error : Error;
+    type Error: Error;
 }
 
-pub trait OutputPinAsync : ErrorType {
+/// Async output pin
+pub trait OutputPinAsync: ErrorType {
+    /// Drives the pin low
     async fn set_low_async(&mut self) -> Result<(), Self::Error>;
 
+    /// Drives the pin high
     async fn set_high_async(&mut self) -> Result<(), Self::Error>;
 
+    /// Drives the pin high or low depending on the given state
     async fn set_state_async(&mut self, state: PinState) -> Result<(), Self::Error>;
 }
 
-pub trait InputPinAsync : ErrorType {
+/// Async input pin
+pub trait InputPinAsync: ErrorType {
+    /// Returns true if the input is high
     async fn is_high_async(&mut self) -> Result<bool, Self::Error>;
 
+    /// Returns true if the input is low
     async fn is_low_async(&mut self) -> Result<bool, Self::Error>;
-}
\ No newline at end of file
+}
+
+/// Implements the async traits for any blocking [embedded_hal] pin, e.g. native GPIOs of the MCU
+/// Calls are directly forwarded to the blocking implementation.
+pub struct AsyncAdapter<P> {
+    pin: P,
+}
+
+impl<P> AsyncAdapter<P> {
+    pub fn new(pin: P) -> Self {
+        Self { pin }
+    }
+
+    /// Returns a reference to the wrapped pin
+    pub fn inner(&mut self) -> &mut P {
+        &mut self.pin
+    }
+
+    /// Returns the wrapped pin
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: embedded_hal::digital::ErrorType> ErrorType for AsyncAdapter<P> {
+    type Error = P::Error;
+}
+
+impl<P: OutputPin> OutputPinAsync for AsyncAdapter<P> {
+    async fn set_low_async(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_low()
+    }
+
+    async fn set_high_async(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_high()
+    }
+
+    async fn set_state_async(&mut self, state: PinState) -> Result<(), Self::Error> {
+        self.pin.set_state(state)
+    }
+}
+
+impl<P: InputPin> InputPinAsync for AsyncAdapter<P> {
+    async fn is_high_async(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_high()
+    }
+
+    async fn is_low_async(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_low()
+    }
+}
@@ -0,0 +1,168 @@
+//! # Shared interrupt line
+//!
+//! The INT outputs of multiple PCA9539 are open-drain, so they may be wire-ORed into a single MCU pin.
+//! The [InterruptDispatcher] waits on this shared line and reads the inputs of the registered expanders.
+//! Changes are published to the [InputChanges] of each device.
+//! ```
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::dispatcher::{DispatchMode, InterruptDispatcher};
+//!# use pca9539::poller::InputChanges;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let expander_74 = PCA9539::new(DummyI2CBus::default(), 0x74, DummyResetPin::default()).await;
+//!# let expander_74 = Mutex::<NoopRawMutex, _>::new(expander_74);
+//!# let expander_75 = PCA9539::new(DummyI2CBus::default(), 0x75, DummyResetPin::default()).await;
+//!# let expander_75 = Mutex::<NoopRawMutex, _>::new(expander_75);
+//! let changes_74 = InputChanges::<NoopRawMutex, 1>::new();
+//! let changes_75 = InputChanges::<NoopRawMutex, 1>::new();
+//!
+//! let mut dispatcher = InterruptDispatcher::<_, _, _, _, 4, 1>::new(DispatchMode::All);
+//! dispatcher.register(&expander_74, &changes_74).unwrap();
+//! dispatcher.register(&expander_75, &changes_75).unwrap();
+//!
+//! // Usually running as separate task using the MCU pin connected to INT
+//! // dispatcher.run(int_pin).await;
+//! dispatcher.dispatch().await.unwrap();
+//! assert_eq!(0b0000_0010, changes_75.state().unwrap().bank0);
+//!# });
+//! ```
+
+use crate::expander::RefreshInputError;
+use crate::lock::ExpanderLock;
+use crate::poller::InputChanges;
+use core::marker::PhantomData;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
+use heapless::Vec;
+
+/// Defines which expanders are read on interrupt
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DispatchMode {
+    /// Reads the inputs of all registered expanders
+    All,
+    /// Stops reading as soon as the INT line is released, as the remaining expanders did not cause
+    /// the interrupt. Expanders are read in order of registration.
+    UntilReleased,
+}
+
+/// Maximum number of registered devices is reached
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CapacityError {}
+
+/// Registered expander and the publisher of its input changes
+struct Device<'a, RAWMUTEX, I2CT, RESET, LOCK, const SUBSCRIBERS: usize>
+where
+    RAWMUTEX: RawMutex,
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    expander: &'a LOCK,
+    changes: &'a InputChanges<RAWMUTEX, SUBSCRIBERS>,
+    bus: PhantomData<(I2CT, RESET)>,
+}
+
+/// Dispatches a shared INT line to up to `DEVICES` expanders
+pub struct InterruptDispatcher<'a, RAWMUTEX, I2CT, RESET, LOCK, const DEVICES: usize, const SUBSCRIBERS: usize>
+where
+    RAWMUTEX: RawMutex,
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    devices: Vec<Device<'a, RAWMUTEX, I2CT, RESET, LOCK, SUBSCRIBERS>, DEVICES>,
+    mode: DispatchMode,
+}
+
+impl<'a, RAWMUTEX, I2CT, RESET, LOCK, const DEVICES: usize, const SUBSCRIBERS: usize>
+    InterruptDispatcher<'a, RAWMUTEX, I2CT, RESET, LOCK, DEVICES, SUBSCRIBERS>
+where
+    RAWMUTEX: RawMutex,
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    pub fn new(mode: DispatchMode) -> Self {
+        Self {
+            devices: Vec::new(),
+            mode,
+        }
+    }
+
+    /// Registers an expander, which input changes are published to the given receivers
+    /// Returns an error if the maximum number of devices is reached.
+    pub fn register(
+        &mut self,
+        expander: &'a LOCK,
+        changes: &'a InputChanges<RAWMUTEX, SUBSCRIBERS>,
+    ) -> Result<(), CapacityError> {
+        self.devices
+            .push(Device {
+                expander,
+                changes,
+                bus: PhantomData,
+            })
+            .map_err(|_| CapacityError {})
+    }
+
+    /// Waits on the shared INT line and dispatches each interrupt
+    /// Failed reads are retried with the next interrupt, as the INT line is not released in this case.
+    pub async fn run<INT>(&self, mut int: INT) -> !
+    where
+        INT: Wait + InputPin,
+    {
+        loop {
+            // Errors of the INT pin are treated as interrupt, so the input state gets re-evaluated
+            let _ = int.wait_for_low().await;
+
+            if self.dispatch_released(&mut int).await.is_err() {
+                // Giving other tasks the chance to run, as INT is still low
+                embassy_futures::yield_now().await;
+            }
+        }
+    }
+
+    /// Reads the inputs of all registered expanders and publishes changes
+    /// Returns the first error after processing all devices.
+    pub async fn dispatch(&self) -> Result<(), RefreshInputError<I2CT>> {
+        self.dispatch_devices(|| false).await
+    }
+
+    /// Reads the inputs of the registered expanders and publishes changes
+    /// In [DispatchMode::UntilReleased] the given INT pin is checked after each expander.
+    /// Returns the first error after processing all devices.
+    pub async fn dispatch_released<INT: InputPin>(&self, int: &mut INT) -> Result<(), RefreshInputError<I2CT>> {
+        match self.mode {
+            DispatchMode::All => self.dispatch_devices(|| false).await,
+            // Errors of the INT pin are treated as still asserted
+            DispatchMode::UntilReleased => self.dispatch_devices(|| int.is_high().unwrap_or(false)).await,
+        }
+    }
+
+    /// Refreshes the devices in order of registration until `released` returns true
+    async fn dispatch_devices<F>(&self, mut released: F) -> Result<(), RefreshInputError<I2CT>>
+    where
+        F: FnMut() -> bool,
+    {
+        let mut result = Ok(());
+
+        for device in &self.devices {
+            if let Err(error) = device.changes.refresh(device.expander, None).await {
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+
+            if released() {
+                break;
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,88 @@
+//! # Dummy peripherals for examples
+//!
+//! Dummy I2C bus, reset pin and delay, which are just used for documentation examples.
+use core::convert::Infallible;
+use embedded_hal::i2c::{ErrorType, Operation, SevenBitAddress};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+/// Input register state of bank 0 reported by the dummy bus
+const INPUT_0: u8 = 0b0000_0010;
+/// Input register state of bank 1 reported by the dummy bus
+const INPUT_1: u8 = 0b0000_0001;
+
+/// Dummy I2C bus, which accepts all writes and returns a static input state
+/// * Bank 0: Pin1 high, all other pins low
+/// * Bank 1: Pin0 high, all other pins low
+///
+/// Polarity registers are stored and read back, so the expander passes a probe.
+#[derive(Default)]
+pub struct DummyI2CBus {
+    /// Last written command
+    command: u8,
+
+    /// Written polarity registers
+    polarity: [u8; 2],
+}
+
+impl ErrorType for DummyI2CBus {
+    type Error = Infallible;
+}
+
+impl I2c<SevenBitAddress> for DummyI2CBus {
+    async fn transaction(
+        &mut self,
+        _address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    self.command = bytes[0];
+
+                    if let (0x04 | 0x05, Some(value)) = (self.command, bytes.get(1)) {
+                        self.polarity[self.command as usize - 0x04] = *value;
+                    }
+                }
+                Operation::Read(buffer) => {
+                    buffer[0] = match self.command {
+                        0x00 => INPUT_0,
+                        0x01 => INPUT_1,
+                        0x04 | 0x05 => self.polarity[self.command as usize - 0x04],
+                        _ => 0x0,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Dummy reset pin, which is ignoring all state changes
+#[derive(Default)]
+pub struct DummyResetPin {}
+
+impl embedded_hal::digital::ErrorType for DummyResetPin {
+    type Error = Infallible;
+}
+
+impl embedded_hal::digital::OutputPin for DummyResetPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Dummy delay, which is just yielding once
+#[derive(Clone, Default)]
+pub struct DummyDelay {}
+
+impl DelayNs for DummyDelay {
+    async fn delay_ns(&mut self, _ns: u32) {
+        embassy_futures::yield_now().await
+    }
+}
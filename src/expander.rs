@@ -7,98 +7,163 @@
 //! see the [pins module](crate::pins).
 //!
 //! ## Setup
-//! [PCA9539] instance is created using a I2CBus implementing the I2C traits of
-//! [embedded-hal](https://docs.rs/embedded-hal/latest/embedded_hal/blocking/i2c/index.html).
+//! [PCA9539] instance is created using a I2CBus implementing the async I2C traits of
+//! [embedded-hal-async](https://docs.rs/embedded-hal-async/latest/embedded_hal_async/i2c/index.html).
 //!```
-//! use pca9539::example::DummyI2CBus;
+//! use pca9539::example::{DummyI2CBus, DummyResetPin};
 //! use pca9539::expander::PCA9539;
 //!
+//!# embassy_futures::block_on(async {
 //! let i2c_bus = DummyI2CBus::default();
 //! // Assuming I2C device address 0x74
-//! let expander = PCA9539::new(i2c_bus, 0x74);
+//! let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# });
 //! ```
 //! ## Changing mode
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::{Bank0, Bank1};
 //!# use pca9539::expander::Mode::{Input, Output};
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::{Pin2, Pin4};
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
 //!#
 //! // Switch Pin02 to input mode
-//! expander.set_mode(Bank0, Pin2, Input).unwrap();
+//! expander.set_mode(Bank0, Pin2, Input).await.unwrap();
 //!
 //! // Switch Pin14 to output mode
-//! expander.set_mode(Bank1, Pin4, Output).unwrap();
+//! expander.set_mode(Bank1, Pin4, Output).await.unwrap();
+//!# });
 //! ```
 //! ## Reading input state
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::Bank0;
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::Pin1;
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
 //!#
-//! expander.refresh_input_state(Bank0).unwrap();
+//! expander.refresh_input_state(Bank0).await.unwrap();
 //! let is_high = expander.is_pin_input_high(Bank0, Pin1);
 //!
 //! assert!(is_high);
+//!# });
+//! ```
+//! ## Glitch filter
+//! Noisy inputs are sampled multiple times per refresh, each pin is resolved by majority vote.
+//! ```
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!#
+//! // Three reads per refresh, pins are high if read high at least twice
+//! expander.set_input_filter(3);
+//!
+//! expander.refresh_input_state(Bank0).await.unwrap();
+//! assert!(expander.is_pin_input_high(Bank0, Pin1));
+//!# });
 //! ```
 //! ## Setting output state
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::Bank0;
 //!# use pca9539::expander::Mode::Output;
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::Pin1;
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
 //!#
-//! expander.set_mode(Bank0, Pin1, Output);
+//! expander.set_mode(Bank0, Pin1, Output).await.unwrap();
 //!
 //! expander.set_state(Bank0, Pin1, true);
-//! expander.write_output_state(Bank0).unwrap();
+//! expander.write_output_state(Bank0).await.unwrap();
 //!
 //! let is_high = expander.is_pin_output_high(Bank0, Pin1);
 //! assert!(is_high);
+//!# });
 //! ```
 //! ## Invert input polarity
 //! PCA9539 has built-in hardware support for inverting input state. See [datasheet](<https://www.ti.com/lit/ds/symlink/pca9539.pdf?ts=1649342250975>)
 //! for more details.
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::Bank0;
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::{Pin1, Pin3};
 //!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!#
+//! expander.reverse_polarity(Bank0, Pin3, true).await.unwrap();
+//!# });
+//! ```
+//! ## Probing
+//! Presence of the device is verified without driving any output, e.g. as power-on self-test.
+//! ```
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::{PCA9539, ProbeError};
+//!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
 //!#
-//! expander.reverse_polarity(Bank0, Pin3, true).unwrap();
+//! match expander.probe().await {
+//!     Ok(()) => {}
+//!     Err(ProbeError::NoDevice(_)) => panic!("PCA9539 missing"),
+//!     Err(ProbeError::UnexpectedDevice) => panic!("Wrong part at address"),
+//!     Err(ProbeError::Bus(_)) => panic!("Bus failure"),
+//! }
+//!# });
+//! ```
+//! ## Batched changes
+//! Multiple changes are collected and written with the minimal number of I2C writes.
+//! ```
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::{Bank0, Bank1};
+//!# use pca9539::expander::Mode::Output;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::{Pin1, Pin2, Pin5};
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let mut  expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!#
+//! expander
+//!     .batch(|b| {
+//!         b.set(Bank0, Pin1, false);
+//!         b.set_mode(Bank0, Pin1, Output);
+//!         b.set_mode(Bank0, Pin2, Output);
+//!         b.set_polarity(Bank1, Pin5, true);
+//!     })
+//!     .await
+//!     .unwrap();
+//!# });
 //! ```
 
-#[cfg(feature = "cortex-m")]
-use crate::guard::CsMutexGuard;
-use crate::guard::LockFreeGuard;
-#[cfg(feature = "spin")]
-use crate::guard::SpinGuard;
-use crate::pins::Pins;
+use crate::diagnostics::Diagnostics;
+use crate::lock::WouldBlock;
+use crate::retry::{NoRetryDelay, RecoveryPolicy, RetryPolicy};
+use crate::timeout::Timeout;
 use bitmaps::Bitmap;
-use core::cell::RefCell;
 use core::fmt::{Debug, Formatter};
-#[cfg(feature = "cortex-m")]
-use cortex_m::interrupt::Mutex as CsMutex;
-use embedded_hal::i2c::{SevenBitAddress};
+use embedded_hal::i2c::{Error, ErrorKind, NoAcknowledgeSource, SevenBitAddress};
+use embedded_hal_async::delay::DelayNs;
 use embedded_hal_async::i2c::I2c;
 use heapless::String;
-#[cfg(feature = "spin")]
-use spin::Mutex as SpinMutex;
 
 /// GPIO bank. PCA9539 has two with 7 pins each
 #[derive(Copy, Clone)]
@@ -120,6 +185,18 @@ pub enum PinID {
     Pin7 = 7,
 }
 
+/// Pin IDs ordered by index
+pub(crate) const PIN_IDS: [PinID; 8] = [
+    PinID::Pin0,
+    PinID::Pin1,
+    PinID::Pin2,
+    PinID::Pin3,
+    PinID::Pin4,
+    PinID::Pin5,
+    PinID::Pin6,
+    PinID::Pin7,
+];
+
 /// GPIO mode
 #[derive(PartialEq, Copy, Clone)]
 pub enum Mode {
@@ -128,10 +205,12 @@ pub enum Mode {
 }
 
 /// Abstraction of [PCA9539](<https://www.ti.com/lit/ds/symlink/pca9539.pdf?ts=1649342250975>) I/O expander
-pub struct PCA9539<B, RESET>
+/// `DELAY` is the timer of the [retry policy](RetryPolicy), see [PCA9539::with_retry_policy].
+pub struct PCA9539<B, RESET, DELAY = NoRetryDelay>
 where
     B: I2c<SevenBitAddress>,
-    RESET: embedded_hal::digital::OutputPin
+    RESET: embedded_hal::digital::OutputPin,
+    DELAY: DelayNs,
 {
     bus: B,
 
@@ -164,6 +243,30 @@ where
     configuration_0: Bitmap<8>,
     /// Second configuration register
     configuration_1: Bitmap<8>,
+
+    /// Registers, which cached state differs from the device, indexed by `command - COMMAND_OUTPUT_0`
+    dirty: Bitmap<6>,
+
+    /// Retry policy of all register reads and writes
+    retry: RetryPolicy<DELAY>,
+
+    /// Recovery policy, disabled if none
+    recovery: Option<RecoveryPolicy<B>>,
+
+    /// Number of consecutive failed operations
+    failures: u8,
+
+    /// Transaction, error and retry counters
+    diagnostics: Diagnostics,
+
+    /// Number of consecutive NACKed operations, after which the device is marked absent. Disabled if none.
+    absent_threshold: Option<u8>,
+
+    /// False if the device was detached, see [PCA9539::reattach]
+    present: bool,
+
+    /// Number of samples per input refresh, resolved by majority vote
+    input_samples: u8,
 }
 
 /// Wrapped I2C error when refreshing input state
@@ -171,7 +274,48 @@ where
 pub enum RefreshInputError<B: I2c> {
     WriteError(B::Error),
     ReadError(B::Error),
-    I2cError(B::Error)
+    I2cError(B::Error),
+    /// Expander is locked by another task, see [WouldBlock]
+    WouldBlock,
+    /// Expander was not available in time, see [TimeoutLock](crate::timeout::TimeoutLock)
+    Timeout,
+    /// Device is detached, see [PCA9539::enable_hot_plug]
+    DeviceAbsent,
+}
+
+/// Error of [PCA9539::probe]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ProbeError<E> {
+    /// No device acknowledged the address
+    NoDevice(E),
+    /// A device answered, but did not behave like a PCA9539, e.g. rejected the register access
+    UnexpectedDevice,
+    /// Any other bus error
+    Bus(E),
+}
+
+impl<E: Error> ProbeError<E> {
+    /// Distinguishes a missing device from other bus errors
+    /// A NACK of the data proves that some device acknowledged the address, but rejects the register access.
+    fn from_bus(error: E) -> Self {
+        match error.kind() {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => ProbeError::UnexpectedDevice,
+            ErrorKind::NoAcknowledge(_) => ProbeError::NoDevice(error),
+            _ => ProbeError::Bus(error),
+        }
+    }
+}
+
+impl<B: I2c> From<Timeout> for RefreshInputError<B> {
+    fn from(_: Timeout) -> Self {
+        RefreshInputError::Timeout
+    }
+}
+
+impl<B: I2c> From<WouldBlock> for RefreshInputError<B> {
+    fn from(_: WouldBlock) -> Self {
+        RefreshInputError::WouldBlock
+    }
 }
 
 impl<B: I2c> embedded_hal::digital::Error for RefreshInputError<B> {
@@ -180,8 +324,6 @@ impl<B: I2c> embedded_hal::digital::Error for RefreshInputError<B> {
     }
 }
 
-impl<B: I2c> crate::digital_hal_async::Error for RefreshInputError<B> {}
-
 const COMMAND_INPUT_0: u8 = 0x00;
 const COMMAND_INPUT_1: u8 = 0x01;
 
@@ -194,10 +336,13 @@ const COMMAND_POLARITY_1: u8 = 0x05;
 const COMMAND_CONF_0: u8 = 0x06;
 const COMMAND_CONF_1: u8 = 0x07;
 
+/// Test patterns of both polarity registers written by [PCA9539::probe]
+const PROBE_PATTERNS: [[u8; 2]; 2] = [[0b0101_0101, 0b1010_1010], [0b1010_1010, 0b0101_0101]];
+
 impl<B, RESET> PCA9539<B, RESET>
 where
     B: I2c<SevenBitAddress>,
-    RESET: embedded_hal::digital::OutputPin
+    RESET: embedded_hal::digital::OutputPin,
 {
     pub async fn new(bus: B, address: u8, reset_pin: RESET) -> Self {
         let mut expander = Self {
@@ -212,6 +357,15 @@ where
             polarity_1: Bitmap::<8>::new(),
             configuration_0: Bitmap::<8>::new(),
             configuration_1: Bitmap::<8>::new(),
+            // Device state is unknown, so the first write of each register is always issued
+            dirty: Bitmap::<6>::mask(6),
+            retry: RetryPolicy::default(),
+            recovery: None,
+            failures: 0,
+            diagnostics: Diagnostics::default(),
+            absent_threshold: None,
+            present: true,
+            input_samples: 1,
         };
 
         expander.reset_pin.set_high().unwrap();
@@ -223,29 +377,166 @@ where
 
         expander
     }
+}
 
-    /// Returns a pins container using Mutex based on critical sections
-    /// Individual pins can be used across threads and interrupts, as long just running on a single core
-    #[cfg(feature = "cortex-m")]
-    pub fn pins_cs_mutex(&mut self) -> Pins<B, CsMutexGuard<B>> {
-        Pins::new(CsMutexGuard::new(CsMutex::new(RefCell::new(self))))
+impl<B, RESET, DELAY> PCA9539<B, RESET, DELAY>
+where
+    B: I2c<SevenBitAddress>,
+    RESET: embedded_hal::digital::OutputPin,
+    DELAY: DelayNs,
+{
+    /// Destroys the expander and returns the bus and reset pin
+    /// The registers of the device are not touched, so pins keep their current state.
+    pub fn release(self) -> (B, RESET) {
+        (self.bus, self.reset_pin)
+    }
+
+    /// Returns the bus, e.g. for temporarily handing it to another routine
+    /// Register states are cached, so any writes to the device by others are not reflected.
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
     }
 
-    /// Returns a pins container using a spin mutex
-    /// This is safe to use across threads and on multi-core applications
-    /// However, this requires a system supporting spin mutexes, which are generally only
-    /// available on systems with Atomic CAS
-    #[cfg(feature = "spin")]
-    pub fn pins_spin_mutex(&mut self) -> Pins<B, SpinGuard<B>> {
-        Pins::new(SpinGuard::new(SpinMutex::new(RefCell::new(self))))
+    /// Sets the retry policy, which applies to all following register reads and writes
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy<DELAY>) {
+        self.retry = policy;
+    }
+
+    /// Returns the expander using the given retry policy, which may be based on another timer
+    pub fn with_retry_policy<D: DelayNs>(self, policy: RetryPolicy<D>) -> PCA9539<B, RESET, D> {
+        PCA9539 {
+            bus: self.bus,
+            address: self.address,
+            reset_pin: self.reset_pin,
+            input_0: self.input_0,
+            input_1: self.input_1,
+            output_0: self.output_0,
+            output_1: self.output_1,
+            polarity_0: self.polarity_0,
+            polarity_1: self.polarity_1,
+            configuration_0: self.configuration_0,
+            configuration_1: self.configuration_1,
+            dirty: self.dirty,
+            retry: policy,
+            recovery: self.recovery,
+            failures: self.failures,
+            diagnostics: self.diagnostics,
+            absent_threshold: self.absent_threshold,
+            present: self.present,
+            input_samples: self.input_samples,
+        }
+    }
+
+    /// Sets the recovery policy, which applies after repeated failures
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy<B>) {
+        self.recovery = Some(policy);
+    }
+
+    /// Returns the transaction, error and retry counters
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics
+    }
+
+    /// Resets all diagnostic counters to zero
+    pub fn reset_diagnostics(&mut self) {
+        self.diagnostics = Diagnostics::default();
+    }
+
+    /// Samples the input register the given number of times per refresh, resolving each pin by majority vote
+    /// Filters single faulty reads of noisy inputs, in contrast to [debouncing](crate::debounce) of bouncing
+    /// contacts. Pins are just high if sampled high more than half of the time, so an odd number is recommended.
+    pub fn set_input_filter(&mut self, samples: u8) {
+        self.input_samples = samples.max(1);
+    }
+
+    /// Enables hot-plug handling for detachable devices
+    /// The device is marked absent after the given number of consecutive operations failed with a NACK. While
+    /// absent, input refreshes and pin operations fail with [RefreshInputError::DeviceAbsent] without any bus
+    /// access, until the device is [reattached](Self::reattach). Register writes of the expander itself are just
+    /// cached in the meantime and applied when reattaching.
+    pub fn enable_hot_plug(&mut self, threshold: u8) {
+        self.absent_threshold = Some(threshold.max(1));
+    }
+
+    /// Returns false if the device was marked absent
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    /// Probes the device and writes all cached registers, marking it present again on success
+    /// Device is expected at its power-up defaults, see [HotPlug](crate::hotplug::HotPlug) for periodic re-probing.
+    pub async fn reattach(&mut self) -> Result<(), ProbeError<B::Error>> {
+        self.probe().await?;
+        self.restore_registers().await.map_err(ProbeError::from_bus)?;
+
+        self.present = true;
+        self.failures = 0;
+        Ok(())
+    }
+
+    /// Resets the device and writes all cached registers again
+    /// The RESET pin is pulsed low, followed by the bus recovery callback of the recovery policy (if any).
+    pub async fn recover(&mut self) -> Result<(), B::Error> {
+        // Pin errors are not propagated, an unresponsive device is revealed by the following writes anyway
+        let _ = self.reset_pin.set_low();
+        let _ = self.reset_pin.set_high();
+
+        if let Some(callback) = self.recovery.as_ref().and_then(|policy| policy.bus_recovery()) {
+            callback(&mut self.bus);
+        }
+
+        // Device is back at its power-up defaults
+        self.restore_registers().await?;
+
+        self.failures = 0;
+        Ok(())
+    }
+
+    /// Writes all cached registers unconditionally, bypassing failure counting, recovery and the presence check
+    async fn restore_registers(&mut self) -> Result<(), B::Error> {
+        self.dirty = Bitmap::<6>::mask(6);
+
+        for command in [
+            COMMAND_OUTPUT_0,
+            COMMAND_OUTPUT_1,
+            COMMAND_POLARITY_0,
+            COMMAND_CONF_0,
+            COMMAND_POLARITY_1,
+            COMMAND_CONF_1,
+        ] {
+            self.try_transfer(&[command, self.register_value(command)], &mut []).await?;
+            self.dirty.set((command - COMMAND_OUTPUT_0) as usize, false);
+        }
+
+        Ok(())
+    }
+
+    /// Confirms a responsive PCA9539 at the address
+    /// Test patterns are written to the polarity registers and read back, the original registers are restored
+    /// afterward. Polarity just affects input readings, so no output is driven while probing.
+    /// Transfers are retried according to the retry policy, but neither count as failed operation nor trigger a
+    /// recovery or hot-plug absence.
+    pub async fn probe(&mut self) -> Result<(), ProbeError<B::Error>> {
+        let original = self.read_polarity_registers().await.map_err(ProbeError::from_bus)?;
+
+        let mut result = Ok(());
+
+        for pattern in PROBE_PATTERNS {
+            result = self.probe_pattern(pattern).await;
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let restored = self.write_polarity_registers(original).await.map_err(ProbeError::from_bus);
+        result.and(restored)
     }
 
     /// Switches the given pin to the input/output mode by adjusting the configuration register
+    /// No I2C write is issued if the configuration register is unchanged.
     pub async fn set_mode(&mut self, bank: Bank, id: PinID, mode: Mode) -> Result<(), B::Error> {
-        match bank {
-            Bank::Bank0 => self.configuration_0.set(id as usize, mode.into()),
-            Bank::Bank1 => self.configuration_1.set(id as usize, mode.into()),
-        };
+        self.cache_mode(bank, id, mode);
         self.write_conf(bank).await
     }
 
@@ -257,10 +548,11 @@ where
             bitset.invert();
         }
 
-        match bank {
-            Bank::Bank0 => self.configuration_0 = bitset,
-            Bank::Bank1 => self.configuration_1 = bitset,
-        };
+        let changed = match bank {
+            Bank::Bank0 => core::mem::replace(&mut self.configuration_0, bitset),
+            Bank::Bank1 => core::mem::replace(&mut self.configuration_1, bitset),
+        } != bitset;
+        self.mark_dirty(Self::conf_command(bank), changed);
         self.write_conf(bank).await
     }
 
@@ -269,13 +561,15 @@ where
     /// Note: This just updates the internal register, to make the changes effective,
     /// an additional call to `write_output_state()` is needed.
     pub fn set_state(&mut self, bank: Bank, id: PinID, is_high: bool) {
-        match bank {
+        let changed = match bank {
             Bank::Bank0 => self.output_0.set(id as usize, is_high),
             Bank::Bank1 => self.output_1.set(id as usize, is_high),
-        };
+        } != is_high;
+        self.mark_dirty(Self::output_command(bank), changed);
     }
 
     /// Sets output state for all pins of a bank
+    /// No I2C write is issued if the output register is unchanged.
     pub async fn set_state_all(&mut self, bank: Bank, is_high: bool) -> Result<(), B::Error> {
         let mut bitset = Bitmap::<8>::new();
 
@@ -283,27 +577,41 @@ where
             bitset.invert();
         }
 
-        match bank {
-            Bank::Bank0 => self.output_0 = bitset,
-            Bank::Bank1 => self.output_1 = bitset,
-        };
+        let changed = match bank {
+            Bank::Bank0 => core::mem::replace(&mut self.output_0, bitset),
+            Bank::Bank1 => core::mem::replace(&mut self.output_1, bitset),
+        } != bitset;
+        self.mark_dirty(Self::output_command(bank), changed);
         self.write_output_state(bank).await
     }
 
     /// Reveres/Resets the input polarity of the given pin
+    /// No I2C write is issued if the polarity register is unchanged.
     pub async fn reverse_polarity(&mut self, bank: Bank, id: PinID, reversed: bool) -> Result<(), B::Error> {
-        match bank {
-            Bank::Bank0 => self.polarity_0.set(id as usize, reversed),
-            Bank::Bank1 => self.polarity_1.set(id as usize, reversed),
-        };
+        self.cache_polarity(bank, id, reversed);
         self.write_polarity(bank).await
     }
 
+    /// Collects mode, polarity and output changes, which are written afterward by a single [flush](Self::flush)
+    /// Output registers are written before configuration, so new output pins start with the desired state.
+    pub async fn batch<F>(&mut self, changes: F) -> Result<(), B::Error>
+    where
+        F: FnOnce(&mut Batch<'_, B, RESET, DELAY>),
+    {
+        changes(&mut Batch { expander: self });
+        self.flush().await
+    }
+
     /// Refreshes the input state of the given bank
+    /// Fails with [RefreshInputError::DeviceAbsent] if the device was marked absent.
     pub async fn refresh_input_state(&mut self, bank: Bank) -> Result<(), RefreshInputError<B>> {
+        if !self.present {
+            return Err(RefreshInputError::DeviceAbsent);
+        }
+
         match bank {
-            Bank::Bank0 => self.input_0 = Bitmap::from_value(self.read_input_register(COMMAND_INPUT_0).await?),
-            Bank::Bank1 => self.input_1 = Bitmap::from_value(self.read_input_register(COMMAND_INPUT_1).await?),
+            Bank::Bank0 => self.input_0 = Bitmap::from_value(self.read_filtered_input(COMMAND_INPUT_0).await?),
+            Bank::Bank1 => self.input_1 = Bitmap::from_value(self.read_filtered_input(COMMAND_INPUT_1).await?),
         };
 
         Ok(())
@@ -320,6 +628,15 @@ where
         }
     }
 
+    /// Returns the cached input register of the given bank
+    /// For a updated result `refresh_input_state()` needs to be called beforehand
+    pub fn input_state(&self, bank: Bank) -> u8 {
+        match bank {
+            Bank::Bank0 => *self.input_0.as_value(),
+            Bank::Bank1 => *self.input_1.as_value(),
+        }
+    }
+
     /// Returns true if the pins output state is set high
     pub fn is_pin_output_high(&self, bank: Bank, id: PinID) -> bool {
         match bank {
@@ -328,39 +645,294 @@ where
         }
     }
 
+    /// Samples the given input register and resolves each bit by majority vote
+    async fn read_filtered_input(&mut self, command: u8) -> Result<u8, RefreshInputError<B>> {
+        let mut votes = [0u8; 8];
+
+        for _ in 0..self.input_samples {
+            let value = self.read_input_register(command).await?;
+
+            for (bit, count) in votes.iter_mut().enumerate() {
+                *count += (value >> bit) & 1;
+            }
+        }
+
+        Ok(votes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count as u16 * 2 > self.input_samples as u16)
+            .fold(0, |value, (bit, _)| value | (1 << bit)))
+    }
+
     /// Reads and returns the given input register
+    /// Fails without bus access if the device was marked absent, e.g. by a previous sample.
     async fn read_input_register(&mut self, command: u8) -> Result<u8, RefreshInputError<B>> {
+        if !self.present {
+            return Err(RefreshInputError::DeviceAbsent);
+        }
+
+        self.read_register(command).await.map_err(|error| self.bus_error(error))
+    }
+
+    /// Wraps the given bus error, reporting [RefreshInputError::DeviceAbsent] if the device was marked absent
+    pub(crate) fn bus_error(&self, error: B::Error) -> RefreshInputError<B> {
+        match self.present {
+            true => RefreshInputError::I2cError(error),
+            false => RefreshInputError::DeviceAbsent,
+        }
+    }
+
+    /// Reads and returns the given register
+    async fn read_register(&mut self, command: u8) -> Result<u8, B::Error> {
         let mut buffer: [u8; 1] = [0x0; 1];
-        self.bus.write_read(self.address, &[command], &mut buffer).await.map_err(RefreshInputError::I2cError)?;
+        self.transfer(&[command], &mut buffer).await?;
         Ok(buffer[0])
     }
 
-    /// Writes the configuration register of the given bank
+    /// Writes the given pattern to both polarity registers and verifies it by reading back
+    async fn probe_pattern(&mut self, pattern: [u8; 2]) -> Result<(), ProbeError<B::Error>> {
+        self.write_polarity_registers(pattern).await.map_err(ProbeError::from_bus)?;
+
+        let read_back = self.read_polarity_registers().await.map_err(ProbeError::from_bus)?;
+
+        if read_back != pattern {
+            return Err(ProbeError::UnexpectedDevice);
+        }
+
+        Ok(())
+    }
+
+    /// Reads both polarity registers, bypassing failure counting and recovery
+    async fn read_polarity_registers(&mut self) -> Result<[u8; 2], B::Error> {
+        let mut values = [0x0; 2];
+        self.try_transfer(&[COMMAND_POLARITY_0], &mut values[0..1]).await?;
+        self.try_transfer(&[COMMAND_POLARITY_1], &mut values[1..2]).await?;
+        Ok(values)
+    }
+
+    /// Writes both polarity registers unconditionally, bypassing the cache, failure counting and recovery
+    async fn write_polarity_registers(&mut self, values: [u8; 2]) -> Result<(), B::Error> {
+        self.try_transfer(&[COMMAND_POLARITY_0, values[0]], &mut []).await?;
+        self.try_transfer(&[COMMAND_POLARITY_1, values[1]], &mut []).await
+    }
+
+    /// Writes all registers, which cached state differs from the device
+    /// Output registers are written first, so pins switched to output mode start with the cached state.
+    pub async fn flush(&mut self) -> Result<(), B::Error> {
+        for bank in [Bank::Bank0, Bank::Bank1] {
+            self.write_output_state(bank).await?;
+        }
+
+        for bank in [Bank::Bank0, Bank::Bank1] {
+            self.write_polarity(bank).await?;
+            self.write_conf(bank).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if any cached register differs from the device state
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Writes the configuration register of the given bank, if changed
     async fn write_conf(&mut self, bank: Bank) -> Result<(), B::Error> {
         match bank {
-            Bank::Bank0 => self
-                .bus
-                .write(self.address, &[COMMAND_CONF_0, *self.configuration_0.as_value()]).await,
-            Bank::Bank1 => self
-                .bus
-                .write(self.address, &[COMMAND_CONF_1, *self.configuration_1.as_value()]).await,
+            Bank::Bank0 => self.write_register(COMMAND_CONF_0, *self.configuration_0.as_value()).await,
+            Bank::Bank1 => self.write_register(COMMAND_CONF_1, *self.configuration_1.as_value()).await,
         }
     }
 
     /// Writes the output register of the given bank
+    /// No I2C write is issued if the register is unchanged since the last successful write.
     pub async fn write_output_state(&mut self, bank: Bank) -> Result<(), B::Error> {
         match bank {
-            Bank::Bank0 => self.bus.write(self.address, &[COMMAND_OUTPUT_0, *self.output_0.as_value()]).await,
-            Bank::Bank1 => self.bus.write(self.address, &[COMMAND_OUTPUT_1, *self.output_1.as_value()]).await,
+            Bank::Bank0 => self.write_register(COMMAND_OUTPUT_0, *self.output_0.as_value()).await,
+            Bank::Bank1 => self.write_register(COMMAND_OUTPUT_1, *self.output_1.as_value()).await,
         }
     }
 
-    /// Writes the polarity register of the given bank
+    /// Writes the polarity register of the given bank, if changed
     async fn write_polarity(&mut self, bank: Bank) -> Result<(), B::Error> {
         match bank {
-            Bank::Bank0 => self.bus.write(self.address, &[COMMAND_POLARITY_0, *self.polarity_0.as_value()]).await,
-            Bank::Bank1 => self.bus.write(self.address, &[COMMAND_POLARITY_1, *self.polarity_1.as_value()]).await,
+            Bank::Bank0 => self.write_register(COMMAND_POLARITY_0, *self.polarity_0.as_value()).await,
+            Bank::Bank1 => self.write_register(COMMAND_POLARITY_1, *self.polarity_1.as_value()).await,
+        }
+    }
+
+    /// Writes the given register if dirty and marks it clean on success
+    /// Registers of absent devices stay dirty, as they are written when reattaching.
+    async fn write_register(&mut self, command: u8, value: u8) -> Result<(), B::Error> {
+        let index = (command - COMMAND_OUTPUT_0) as usize;
+
+        if !self.dirty.get(index) || !self.present {
+            return Ok(());
         }
+
+        self.transfer(&[command, value], &mut []).await?;
+        self.dirty.set(index, false);
+        Ok(())
+    }
+
+    /// Transfers the given bytes and recovers the device after repeated failures
+    async fn transfer(&mut self, bytes: &[u8], buffer: &mut [u8]) -> Result<(), B::Error> {
+        // Recovery has failed before
+        if self.is_recovery_due() {
+            self.recover().await?;
+        }
+
+        let error = match self.try_transfer(bytes, buffer).await {
+            Ok(()) => {
+                self.failures = 0;
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+
+        self.failures = self.failures.saturating_add(1);
+        self.detect_absence(error.kind());
+
+        if self.is_recovery_due() {
+            // Errors are ignored, as the recovery is repeated before the next operation
+            let _ = self.recover().await;
+        }
+
+        Err(error)
+    }
+
+    /// Returns true if the recovery policy is exceeded by the consecutive failures
+    /// Absent devices are not recovered, but reattached.
+    fn is_recovery_due(&self) -> bool {
+        self.present && self.recovery.as_ref().is_some_and(|policy| policy.is_due(self.failures))
+    }
+
+    /// Marks the device absent, if the consecutive failures exceed the hot-plug threshold and the device did not ACK
+    fn detect_absence(&mut self, kind: ErrorKind) {
+        let is_threshold_reached = self.absent_threshold.is_some_and(|threshold| self.failures >= threshold);
+
+        if is_threshold_reached && matches!(kind, ErrorKind::NoAcknowledge(_)) {
+            self.present = false;
+        }
+    }
+
+    /// Writes the given bytes, followed by a read if the buffer is not empty
+    /// Failed transfers are repeated according to the retry policy.
+    async fn try_transfer(&mut self, bytes: &[u8], buffer: &mut [u8]) -> Result<(), B::Error> {
+        let mut attempt = 1;
+
+        loop {
+            self.diagnostics.record_transaction(!buffer.is_empty());
+
+            let result = if buffer.is_empty() {
+                self.bus.write(self.address, bytes).await
+            } else {
+                self.bus.write_read(self.address, bytes, buffer).await
+            };
+
+            if let Err(error) = &result {
+                self.diagnostics.record_error(bytes[0], error.kind());
+            }
+
+            match result {
+                Err(error) if self.retry.should_retry(attempt, error.kind()) => {
+                    self.diagnostics.record_retry();
+                    self.retry.wait().await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Updates the cached configuration register without writing it
+    fn cache_mode(&mut self, bank: Bank, id: PinID, mode: Mode) {
+        let changed = match bank {
+            Bank::Bank0 => self.configuration_0.set(id as usize, mode.into()),
+            Bank::Bank1 => self.configuration_1.set(id as usize, mode.into()),
+        } != mode.into();
+        self.mark_dirty(Self::conf_command(bank), changed);
+    }
+
+    /// Updates the cached polarity register without writing it
+    fn cache_polarity(&mut self, bank: Bank, id: PinID, reversed: bool) {
+        let changed = match bank {
+            Bank::Bank0 => self.polarity_0.set(id as usize, reversed),
+            Bank::Bank1 => self.polarity_1.set(id as usize, reversed),
+        } != reversed;
+        self.mark_dirty(Self::polarity_command(bank), changed);
+    }
+
+    /// Marks the given register as dirty, if its cached state has changed
+    fn mark_dirty(&mut self, command: u8, changed: bool) {
+        if changed {
+            self.dirty.set((command - COMMAND_OUTPUT_0) as usize, true);
+        }
+    }
+
+    /// Returns the cached value of the given output, polarity or configuration register
+    fn register_value(&self, command: u8) -> u8 {
+        match command {
+            COMMAND_OUTPUT_0 => *self.output_0.as_value(),
+            COMMAND_OUTPUT_1 => *self.output_1.as_value(),
+            COMMAND_POLARITY_0 => *self.polarity_0.as_value(),
+            COMMAND_POLARITY_1 => *self.polarity_1.as_value(),
+            COMMAND_CONF_0 => *self.configuration_0.as_value(),
+            _ => *self.configuration_1.as_value(),
+        }
+    }
+
+    fn output_command(bank: Bank) -> u8 {
+        match bank {
+            Bank::Bank0 => COMMAND_OUTPUT_0,
+            Bank::Bank1 => COMMAND_OUTPUT_1,
+        }
+    }
+
+    fn polarity_command(bank: Bank) -> u8 {
+        match bank {
+            Bank::Bank0 => COMMAND_POLARITY_0,
+            Bank::Bank1 => COMMAND_POLARITY_1,
+        }
+    }
+
+    fn conf_command(bank: Bank) -> u8 {
+        match bank {
+            Bank::Bank0 => COMMAND_CONF_0,
+            Bank::Bank1 => COMMAND_CONF_1,
+        }
+    }
+}
+
+/// Collects register changes of [PCA9539::batch]
+pub struct Batch<'a, B, RESET, DELAY = NoRetryDelay>
+where
+    B: I2c<SevenBitAddress>,
+    RESET: embedded_hal::digital::OutputPin,
+    DELAY: DelayNs,
+{
+    expander: &'a mut PCA9539<B, RESET, DELAY>,
+}
+
+impl<B, RESET, DELAY> Batch<'_, B, RESET, DELAY>
+where
+    B: I2c<SevenBitAddress>,
+    RESET: embedded_hal::digital::OutputPin,
+    DELAY: DelayNs,
+{
+    /// Sets the output state of the given pin
+    pub fn set(&mut self, bank: Bank, id: PinID, is_high: bool) {
+        self.expander.set_state(bank, id, is_high);
+    }
+
+    /// Switches the given pin to input/output mode
+    pub fn set_mode(&mut self, bank: Bank, id: PinID, mode: Mode) {
+        self.expander.cache_mode(bank, id, mode);
+    }
+
+    /// Reverses/Resets the input polarity of the given pin
+    pub fn set_polarity(&mut self, bank: Bank, id: PinID, reversed: bool) {
+        self.expander.cache_polarity(bank, id, reversed);
     }
 }
 
@@ -379,16 +951,29 @@ impl<B: I2c> Debug for RefreshInputError<B> {
             RefreshInputError::WriteError(_) => f.write_str("RefreshInputError::WriteError"),
             RefreshInputError::ReadError(_) => f.write_str("RefreshInputError::ReadError"),
             RefreshInputError::I2cError(_) => f.write_str("RefreshInputError::I2cError"),
+            RefreshInputError::WouldBlock => f.write_str("RefreshInputError::WouldBlock"),
+            RefreshInputError::Timeout => f.write_str("RefreshInputError::Timeout"),
+            RefreshInputError::DeviceAbsent => f.write_str("RefreshInputError::DeviceAbsent"),
         }
     }
 }
 
 impl<B: I2c> RefreshInputError<B> {
-    pub fn to_string(&self) -> String<10> {
+    pub fn to_string(&self) -> String<12> {
         match self {
             RefreshInputError::WriteError(_) => String::try_from("WriteError").unwrap(),
             RefreshInputError::ReadError(_) => String::try_from("ReadError").unwrap(),
             RefreshInputError::I2cError(_) => String::try_from("I2cError").unwrap(),
+            RefreshInputError::WouldBlock => String::try_from("WouldBlock").unwrap(),
+            RefreshInputError::Timeout => String::try_from("Timeout").unwrap(),
+            RefreshInputError::DeviceAbsent => String::try_from("DeviceAbsent").unwrap(),
         }
     }
+    /// True if the error was reported by the bus, so the operation may succeed when repeated
+    pub(crate) fn is_bus_error(&self) -> bool {
+        matches!(
+            self,
+            RefreshInputError::WriteError(_) | RefreshInputError::ReadError(_) | RefreshInputError::I2cError(_)
+        )
+    }
 }
@@ -0,0 +1,125 @@
+//! # Hot-plug handling
+//!
+//! Expanders on detachable daughterboards are marked absent after repeated NACKs, once hot-plug handling is
+//! enabled by [PCA9539::enable_hot_plug](crate::expander::PCA9539::enable_hot_plug). While absent, pin operations
+//! fail with [DeviceAbsent](crate::expander::RefreshInputError::DeviceAbsent) without accessing the bus.
+//!
+//! The [HotPlug] monitor periodically re-probes absent devices. Once a device is back, its cached configuration
+//! and output registers are re-applied, before presence changes are published to its subscribers.
+//! Present devices are not probed, their absence is detected by failing operations.
+//! ```
+//!# use embassy_futures::select::select;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyDelay, DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::hotplug::{HotPlug, Presence};
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! let mut expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!
+//! // Absent after three consecutive NACKs
+//! expander.enable_hot_plug(3);
+//! let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//!
+//! // Monitor supporting up to two subscribers
+//! let hot_plug = HotPlug::<NoopRawMutex, 2>::new();
+//! let mut presence = hot_plug.receiver().unwrap();
+//!
+//! let subscriber = async {
+//!     assert_eq!(Presence::Present, presence.changed().await);
+//! };
+//!
+//! // Re-probing every 500 ms, usually spawned as separate task
+//! let monitoring = hot_plug.run(&expander, DummyDelay::default(), 500_000);
+//!
+//! select(subscriber, monitoring).await;
+//!# });
+//! ```
+
+use crate::lock::ExpanderLock;
+use crate::timeout::Timeout;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::watch::{Receiver, Watch};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+/// Presence of a detachable device
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Presence {
+    Present,
+    Absent,
+}
+
+/// Re-probes absent devices and publishes presence changes to up to `SUBSCRIBERS` receivers
+pub struct HotPlug<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> {
+    presence: Watch<RAWMUTEX, Presence, SUBSCRIBERS>,
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> HotPlug<RAWMUTEX, SUBSCRIBERS> {
+    pub const fn new() -> Self {
+        Self { presence: Watch::new() }
+    }
+
+    /// Returns a new receiver of presence changes. None if the maximum number of subscribers is reached.
+    pub fn receiver(&self) -> Option<Receiver<'_, RAWMUTEX, Presence, SUBSCRIBERS>> {
+        self.presence.receiver()
+    }
+
+    /// Returns the last published presence
+    pub fn presence(&self) -> Option<Presence> {
+        self.presence.try_get()
+    }
+
+    /// Checks the presence every `period_us` microseconds
+    /// Checks failing due to a lock timeout are repeated with the next period.
+    pub async fn run<I2CT, RESET, LOCK, DELAY>(&self, expander: &LOCK, mut delay: DELAY, period_us: u32) -> !
+    where
+        I2CT: I2c,
+        RESET: OutputPin,
+        LOCK: ExpanderLock<I2CT, RESET>,
+        DELAY: DelayNs,
+    {
+        loop {
+            let _ = self.check(expander).await;
+            delay.delay_us(period_us).await;
+        }
+    }
+
+    /// Reattaches the device if marked absent and publishes the presence, if changed
+    /// Returns the current presence. Fails with [Timeout] if the lock timed out, nothing is published in this case.
+    pub async fn check<I2CT, RESET, LOCK>(&self, expander: &LOCK) -> Result<Presence, Timeout>
+    where
+        I2CT: I2c,
+        RESET: OutputPin,
+        LOCK: ExpanderLock<I2CT, RESET>,
+    {
+        let presence = {
+            let mut expander = expander.lock_timeout().await?;
+
+            if !expander.is_present() {
+                // Device is still absent on failure
+                let _ = expander.reattach().await;
+            }
+
+            match expander.is_present() {
+                true => Presence::Present,
+                false => Presence::Absent,
+            }
+        };
+
+        if self.presence.try_get() != Some(presence) {
+            self.presence.sender().send(presence);
+        }
+
+        Ok(presence)
+    }
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> Default for HotPlug<RAWMUTEX, SUBSCRIBERS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
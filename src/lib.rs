@@ -2,36 +2,60 @@
 //!
 //! Abstraction for I/O expander [PCA9539](<https://www.ti.com/lit/ds/symlink/pca9539.pdf?ts=1649342250975>).
 //! This crate offers the following features:
-//! * Individual pin instances, fully implementing [digital::v2 traits of embedded_hal](https://docs.rs/embedded-hal/latest/embedded_hal/digital/v2/index.html)
+//! * Individual pin instances, fully implementing [digital traits of embedded_hal](https://docs.rs/embedded-hal/latest/embedded_hal/digital/index.html)
 //! * Central I/O control, s. [PCA9539 module](crate::expander)
 //! * Two state management modes for reduced I2C overhead, s. [pins module](crate::pins)
-//! * Three concurrency models, s. [concurrency section](crate::pins#concurrency)
+//! * Pluggable locking backends for different concurrency models, s. [concurrency section](crate::pins#concurrency)
+//! * Optional timeouts for bus transactions and lock acquisition, s. [timeout module](crate::timeout)
+//! * Configurable retries of transient bus errors and device recovery, s. [retry module](crate::retry)
+//! * Diagnostic counters for reporting the I/O health, s. [diagnostics module](crate::diagnostics)
+//! * Address scan for a variable number of expanders, s. [scan module](crate::scan)
+//! * Hot-plug handling for detachable expanders, s. [hotplug module](crate::hotplug)
 //! * no_std support
 //!
 //! ## Example
 //! ```
-//! use pca9539::example::DummyI2CBus;
+//! use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//! use embassy_sync::mutex::Mutex;
+//! use pca9539::example::{DummyI2CBus, DummyResetPin};
 //! use pca9539::expander::Bank::Bank0;
 //! use pca9539::expander::PCA9539;
 //! use pca9539::expander::PinID::Pin1;
-//! use embedded_hal::digital::v2::InputPin;
+//! use pca9539::pins::Pins;
+//! use embedded_hal::digital::InputPin;
 //!
+//!# embassy_futures::block_on(async {
 //! let i2c_bus = DummyI2CBus::default();
-//! let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
+//! let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//! let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
 //!
-//! let pin01 = pins.get_pin(Bank0, Pin1);
+//! let mut pin01 = pins.get_pin(Bank0, Pin1).unwrap();
 //! assert!(pin01.is_high().unwrap());
+//!# });
+//! ```
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(feature = "strict", deny(warnings))]
+#![allow(async_fn_in_trait)]
 
 extern crate embedded_hal;
 
+pub mod array;
+pub mod debounce;
+pub mod diagnostics;
+pub mod digital_hal_async;
+pub mod dispatcher;
 #[cfg(feature = "example")]
+pub mod example;
 pub mod expander;
-pub mod guard;
+pub mod hotplug;
+pub mod lock;
 pub mod pins;
-pub mod digital_hal_async;
+pub mod poller;
+pub mod retry;
+pub mod scan;
+pub mod timeout;
+pub mod wait;
 
 pub(crate) mod pin_refreshable;
 pub(crate) mod pin_regular;
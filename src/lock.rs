@@ -0,0 +1,253 @@
+//! # Locking backends
+//!
+//! Pins share the expander by reference, so access is synchronized by an [ExpanderLock]. The backend is chosen by
+//! wrapping the expander accordingly, see [concurrency section](crate::pins#concurrency) for more details:
+//! * [RefCell]: Single-threaded and zero cost, but neither Send or Sync
+//! * [Mutex]: Async mutex of embassy, concurrency model defined by its raw mutex
+//! * `CsMutex`: Mutex based on critical sections (feature `critical-section`)
+//! * `spin::Mutex`: Spin mutex for systems with atomic CAS (feature `spin`)
+//! ```
+//!# use core::cell::RefCell;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!# use pca9539::pins::Pins;
+//!# use embedded_hal::digital::InputPin;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! let expander = RefCell::new(PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await);
+//! let pins = Pins::new(&expander);
+//!
+//! let mut pin01 = pins.get_pin(Bank0, Pin1).unwrap();
+//! assert!(pin01.is_high().unwrap());
+//!# });
+//! ```
+
+use crate::expander::PCA9539;
+use crate::timeout::Timeout;
+use core::cell::{RefCell, RefMut};
+use core::ops::DerefMut;
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+#[cfg(feature = "critical-section")]
+use crate::retry::NoRetryDelay;
+#[cfg(feature = "critical-section")]
+use core::cell::{Cell, UnsafeCell};
+#[cfg(feature = "critical-section")]
+use core::ops::Deref;
+#[cfg(feature = "critical-section")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// Expander is locked by another task
+/// Returned by the blocking [embedded_hal] impls of pins, which fail fast instead of waiting for the lock.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WouldBlock {}
+
+impl embedded_hal::digital::Error for WouldBlock {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
+
+/// Synchronizes the access of pins to the shared expander
+pub trait ExpanderLock<I2CT: I2c, RESET: OutputPin> {
+    /// Raw mutex protecting the ownership of pins, needs to match the concurrency model of the lock
+    type RawMutex: RawMutex;
+
+    /// Timer of the expander's retry policy
+    type RetryDelay: DelayNs;
+
+    /// Grants exclusive access to the expander while alive
+    type Guard<'a>: DerefMut<Target = PCA9539<I2CT, RESET, Self::RetryDelay>>
+    where
+        Self: 'a;
+
+    /// Returns the guard if the expander is not locked
+    fn try_lock(&self) -> Option<Self::Guard<'_>>;
+
+    /// Waits until the expander is available
+    /// By default the lock is polled, yielding to other tasks in between.
+    async fn lock(&self) -> Self::Guard<'_> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+
+            embassy_futures::yield_now().await;
+        }
+    }
+
+    /// Waits until the expander is available, failing if a timeout is configured and elapsed
+    /// Locks without timeout are waiting forever, see [TimeoutLock](crate::timeout::TimeoutLock).
+    async fn lock_timeout(&self) -> Result<Self::Guard<'_>, Timeout> {
+        Ok(self.lock().await)
+    }
+}
+
+/// Single-threaded backend without any locking overhead
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> ExpanderLock<I2CT, RESET>
+    for RefCell<PCA9539<I2CT, RESET, DELAY>>
+{
+    type RawMutex = NoopRawMutex;
+    type RetryDelay = DELAY;
+
+    type Guard<'a>
+        = RefMut<'a, PCA9539<I2CT, RESET, DELAY>>
+    where
+        Self: 'a;
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.try_borrow_mut().ok()
+    }
+}
+
+/// Async mutex of embassy, waiting tasks are woken up when the mutex is released
+impl<R: RawMutex, I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> ExpanderLock<I2CT, RESET>
+    for Mutex<R, PCA9539<I2CT, RESET, DELAY>>
+{
+    type RawMutex = R;
+    type RetryDelay = DELAY;
+
+    type Guard<'a>
+        = MutexGuard<'a, R, PCA9539<I2CT, RESET, DELAY>>
+    where
+        Self: 'a;
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        Mutex::try_lock(self).ok()
+    }
+
+    async fn lock(&self) -> Self::Guard<'_> {
+        Mutex::lock(self).await
+    }
+}
+
+/// Mutex based on critical sections, which may be used across executors and interrupts
+/// Critical sections are just held while acquiring or releasing the lock, but not during I2C transfers.
+#[cfg(feature = "critical-section")]
+pub struct CsMutex<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs = NoRetryDelay> {
+    locked: critical_section::Mutex<Cell<bool>>,
+    expander: UnsafeCell<PCA9539<I2CT, RESET, DELAY>>,
+}
+
+// Access to the expander is exclusive, guarded by the locked flag
+#[cfg(feature = "critical-section")]
+unsafe impl<I2CT: I2c + Send, RESET: OutputPin + Send, DELAY: DelayNs + Send> Sync
+    for CsMutex<I2CT, RESET, DELAY>
+{
+}
+
+#[cfg(feature = "critical-section")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> CsMutex<I2CT, RESET, DELAY> {
+    pub const fn new(expander: PCA9539<I2CT, RESET, DELAY>) -> Self {
+        Self {
+            locked: critical_section::Mutex::new(Cell::new(false)),
+            expander: UnsafeCell::new(expander),
+        }
+    }
+
+    /// Consumes the mutex and returns the expander
+    pub fn into_inner(self) -> PCA9539<I2CT, RESET, DELAY> {
+        self.expander.into_inner()
+    }
+}
+
+/// Exclusive access to the expander of a [CsMutex]
+#[cfg(feature = "critical-section")]
+pub struct CsMutexGuard<'a, I2CT: I2c, RESET: OutputPin, DELAY: DelayNs = NoRetryDelay> {
+    mutex: &'a CsMutex<I2CT, RESET, DELAY>,
+}
+
+#[cfg(feature = "critical-section")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> Deref for CsMutexGuard<'_, I2CT, RESET, DELAY> {
+    type Target = PCA9539<I2CT, RESET, DELAY>;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: The guard is the only accessor while the locked flag is set
+        unsafe { &*self.mutex.expander.get() }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> DerefMut for CsMutexGuard<'_, I2CT, RESET, DELAY> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: The guard is the only accessor while the locked flag is set
+        unsafe { &mut *self.mutex.expander.get() }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> Drop for CsMutexGuard<'_, I2CT, RESET, DELAY> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| self.mutex.locked.borrow(cs).set(false));
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> ExpanderLock<I2CT, RESET> for CsMutex<I2CT, RESET, DELAY> {
+    type RawMutex = CriticalSectionRawMutex;
+    type RetryDelay = DELAY;
+
+    type Guard<'a>
+        = CsMutexGuard<'a, I2CT, RESET, DELAY>
+    where
+        Self: 'a;
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        critical_section::with(|cs| {
+            let locked = self.locked.borrow(cs);
+
+            if locked.get() {
+                return None;
+            }
+
+            locked.set(true);
+            Some(CsMutexGuard { mutex: self })
+        })
+    }
+}
+
+/// Spin mutex, which is safe to use across threads and on multi-core applications
+/// Waiting tasks yield instead of spinning, so the lock holder may progress on the same executor.
+#[cfg(feature = "spin")]
+impl<I2CT: I2c, RESET: OutputPin, DELAY: DelayNs> ExpanderLock<I2CT, RESET>
+    for spin::Mutex<PCA9539<I2CT, RESET, DELAY>>
+{
+    type RawMutex = SpinRawMutex;
+    type RetryDelay = DELAY;
+
+    type Guard<'a>
+        = spin::MutexGuard<'a, PCA9539<I2CT, RESET, DELAY>>
+    where
+        Self: 'a;
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        spin::Mutex::try_lock(self)
+    }
+}
+
+/// Raw mutex based on a spin lock, used for the pin ownership of [spin::Mutex] backends
+#[cfg(feature = "spin")]
+pub struct SpinRawMutex {
+    lock: spin::Mutex<()>,
+}
+
+// Safety: The spin lock grants exclusive access across threads and cores
+#[cfg(feature = "spin")]
+unsafe impl RawMutex for SpinRawMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        lock: spin::Mutex::new(()),
+    };
+
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = self.lock.lock();
+        f()
+    }
+}
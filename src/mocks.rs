@@ -1,30 +1,75 @@
-use embedded_hal::blocking::i2c::{Read, SevenBitAddress, Write};
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation, SevenBitAddress};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
 use mockall::mock;
+use std::collections::HashMap;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 #[allow(unused)]
-pub enum WriteError {
+pub enum BusError {
     Error1,
+    Nack,
+    DataNack,
+    ArbitrationLoss,
 }
 
-#[derive(Debug, PartialEq)]
-#[allow(unused)]
-pub enum ReadError {
-    Error1,
+impl embedded_hal::i2c::Error for BusError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            BusError::Error1 => ErrorKind::Other,
+            BusError::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            BusError::DataNack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            BusError::ArbitrationLoss => ErrorKind::ArbitrationLoss,
+        }
+    }
 }
 
 mock! {
     #[derive(Debug)]
-    pub I2CBus{}
+    pub I2CBus {
+        pub fn write(&mut self, address: SevenBitAddress, bytes: &[u8]) -> Result<(), BusError>;
+        pub fn write_read(&mut self, address: SevenBitAddress, bytes: &[u8], buffer: &mut [u8]) -> Result<(), BusError>;
+    }
+}
+
+impl ErrorType for MockI2CBus {
+    type Error = BusError;
+}
+
+impl I2c<SevenBitAddress> for MockI2CBus {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        match operations {
+            [Operation::Write(bytes)] => MockI2CBus::write(self, address, bytes),
+            [Operation::Write(bytes), Operation::Read(buffer)] => {
+                MockI2CBus::write_read(self, address, bytes, buffer)
+            }
+            _ => unimplemented!("Unsupported I2C transaction"),
+        }
+    }
+}
+
+/// Reset pin, which is ignoring all state changes
+pub struct DummyPin {}
 
-    impl Write<SevenBitAddress> for I2CBus {
-        type Error = WriteError;
-        fn write(&mut self, address: SevenBitAddress, bytes: &[u8]) -> Result<(), WriteError>;
+impl embedded_hal::digital::ErrorType for DummyPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for DummyPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
     }
 
-    impl Read<SevenBitAddress> for I2CBus {
-        type Error = ReadError;
-        fn read(&mut self, address: SevenBitAddress, buffer: &mut [u8]) -> Result<(), ReadError>;
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
     }
 }
 
@@ -56,32 +101,47 @@ impl BusMockBuilder {
         self
     }
 
-    pub fn expect_read(mut self, times: usize, data: u8) -> Self {
-        self.bus.expect_read().times(times).returning(move |address, buffer| {
-            assert_eq!(0x74, address);
-            assert_eq!(1, buffer.len());
-            buffer[0] = data;
+    pub fn expect_read(mut self, times: usize, command: u8, data: u8) -> Self {
+        self.bus
+            .expect_write_read()
+            .times(times)
+            .returning(move |address, bytes, buffer| {
+                assert_eq!(0x74, address);
+                assert_eq!(&[command], bytes);
+                assert_eq!(1, buffer.len());
+                buffer[0] = data;
 
-            Ok(())
-        });
+                Ok(())
+            });
 
         self
     }
 
-    pub fn write_error(mut self, command: u8) -> Self {
+    pub fn write_error(self, command: u8) -> Self {
+        self.write_failure(command, BusError::Error1)
+    }
+
+    /// Expect a single write of the given command, failing with the given error
+    pub fn write_failure(mut self, command: u8, error: BusError) -> Self {
         self.bus.expect_write().times(1).returning(move |address, buffer| {
             assert_eq!(0x74, address);
             assert_eq!(command, buffer[0]);
-            Err(WriteError::Error1)
+            Err(error)
         });
 
         self
     }
 
-    pub fn read_error(mut self) -> Self {
-        self.bus.expect_read().times(1).returning(move |address, _| {
+    pub fn read_error(self, command: u8) -> Self {
+        self.read_failure(command, BusError::Error1)
+    }
+
+    /// Expect a single read of the given register, failing with the given error
+    pub fn read_failure(mut self, command: u8, error: BusError) -> Self {
+        self.bus.expect_write_read().times(1).returning(move |address, bytes, _| {
             assert_eq!(0x74, address);
-            Err(ReadError::Error1)
+            assert_eq!(&[command], bytes);
+            Err(error)
         });
 
         self
@@ -91,3 +151,183 @@ impl BusMockBuilder {
         self.bus
     }
 }
+
+/// Output pin recording all set levels
+pub struct RecordingPin<'a> {
+    pub levels: &'a RefCell<Vec<bool>>,
+}
+
+impl embedded_hal::digital::ErrorType for RecordingPin<'_> {
+    type Error = Infallible;
+}
+
+impl OutputPin for RecordingPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.levels.borrow_mut().push(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.levels.borrow_mut().push(true);
+        Ok(())
+    }
+}
+
+/// Bus emulating the polarity registers of expanders at the given addresses
+/// Foreign devices acknowledge all transfers, but always read 0xFF. Other addresses are not acknowledged.
+#[derive(Default)]
+pub struct ScanBus {
+    pub expanders: Vec<u8>,
+    pub foreign: Vec<u8>,
+    pub failing: Vec<u8>,
+
+    /// Devices acknowledging their address, but rejecting any register access
+    pub rejecting: Vec<u8>,
+
+    /// Polarity registers by address
+    pub polarity: HashMap<u8, [u8; 2]>,
+
+    /// Last written command
+    pub command: u8,
+}
+
+impl ErrorType for ScanBus {
+    type Error = BusError;
+}
+
+impl I2c<SevenBitAddress> for ScanBus {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if self.failing.contains(&address) {
+            return Err(BusError::ArbitrationLoss);
+        }
+
+        if self.rejecting.contains(&address) {
+            return Err(BusError::DataNack);
+        }
+
+        if self.foreign.contains(&address) {
+            for operation in operations {
+                if let Operation::Read(buffer) = operation {
+                    buffer.fill(0xFF);
+                }
+            }
+
+            return Ok(());
+        }
+
+        if !self.expanders.contains(&address) {
+            return Err(BusError::Nack);
+        }
+
+        let polarity = self.polarity.entry(address).or_default();
+
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    self.command = bytes[0];
+
+                    if bytes.len() == 2 {
+                        polarity[(self.command - 0x04) as usize] = bytes[1];
+                    }
+                }
+                Operation::Read(buffer) => buffer[0] = polarity[(self.command - 0x04) as usize],
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Bus, which never completes any transaction
+pub struct HangingBus {}
+
+impl ErrorType for HangingBus {
+    type Error = BusError;
+}
+
+impl I2c<SevenBitAddress> for HangingBus {
+    async fn transaction(
+        &mut self,
+        _address: SevenBitAddress,
+        _operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+}
+
+/// Delay, which is returning immediately
+#[derive(Clone)]
+pub struct NoDelay {}
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// INT pin, which is reporting a change immediately
+pub struct DummyInterruptPin {}
+
+impl embedded_hal::digital::ErrorType for DummyInterruptPin {
+    type Error = Infallible;
+}
+
+impl Wait for DummyInterruptPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Delay recording all requested intervals in microseconds, yielding once per call
+#[derive(Clone)]
+pub struct RecordingDelay<'a> {
+    pub intervals: &'a RefCell<Vec<u32>>,
+}
+
+impl DelayNs for RecordingDelay<'_> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay_us(ns / 1000).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        self.intervals.borrow_mut().push(us);
+        embassy_futures::yield_now().await
+    }
+}
+
+/// INT pin reporting the given sequence of levels, one per call of is_high()
+pub struct SequencePin {
+    pub levels: Vec<bool>,
+}
+
+impl embedded_hal::digital::ErrorType for SequencePin {
+    type Error = Infallible;
+}
+
+impl InputPin for SequencePin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.levels.remove(0))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
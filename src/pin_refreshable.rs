@@ -1,15 +1,15 @@
-use crate::expander::{PCA9539, Bank, Mode, PinID, RefreshInputError};
-use crate::pins::{Input, Output, Pin, RefreshMode};
-use core::convert::Infallible;
-use core::marker::PhantomData;
-use embedded_hal_async::i2c::I2c;
-use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
-use embedded_hal::digital;
-use embassy_sync::mutex::Mutex;
-use embassy_sync::blocking_mutex::raw::RawMutex;
-use crate::digital_hal_async::{InputPinAsync, OutputPinAsync};
 use crate::digital_hal_async;
-
+use crate::digital_hal_async::{InputPinAsync, OutputPinAsync};
+use crate::expander::{Bank, Mode, RefreshInputError};
+use crate::lock::ExpanderLock;
+use crate::pins::{
+    Input, InputMode, Output, OutputMode, Pin, PinLocation, PinMode, RefreshMode, RegularAccessMode,
+};
+use crate::wait::WaitStrategy;
+use embedded_hal::digital;
+use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
 
 /// Trait for refreshable pins in output mode
 pub trait RefreshableOutputPin {
@@ -33,71 +33,67 @@ pub trait RefreshableInputPin {
     async fn refresh_all(&self) -> Result<(), Self::Error>;
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-    pub fn refreshable(expander: &'a Mutex<RAWMUTEX, Option<PCA9539<I2CT, RESET>>>, bank: Bank, id: PinID) -> Self {
-        Self {
-            expander,
-            reset: PhantomData,
-            bank,
-            id,
-            access_mode: PhantomData,
-            mode: PhantomData,
-        }
-    }
-
     /// Refreshes the input state of the given bank
     async fn refresh(&self, bank: Bank) -> Result<(), RefreshInputError<I2CT>> {
-        let mut expander = self.expander.lock().await;
-        expander.as_mut().unwrap().refresh_input_state(bank).await
+        let mut expander = self.acquire().await?;
+        expander.refresh_input_state(bank).await
     }
+}
 
-    pub async fn into_input_pin(self) -> Result<Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>, I2CT::Error> {
+impl<'a, I2CT, RESET, LOCK, MODE, WAIT, LOC: PinLocation> Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    MODE: PinMode,
+{
+    pub async fn into_input_pin(
+        self,
+    ) -> Result<Pin<'a, I2CT, RESET, LOCK, Input, RefreshMode, WAIT, LOC>, RefreshInputError<I2CT>> {
         self.change_mode(Mode::Input).await?;
 
-        Ok(Pin {
-            expander: self.expander,
-            bank: self.bank,
-            id: self.id,
-            mode: PhantomData,
-            access_mode: PhantomData,
-            reset: PhantomData
-        })
+        Ok(self.convert())
     }
 
-    pub async fn into_output_pin(self, state: PinState) -> Result<Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>, I2CT::Error> {
+    pub async fn into_output_pin(
+        self,
+        state: PinState,
+    ) -> Result<Pin<'a, I2CT, RESET, LOCK, Output, RefreshMode, WAIT, LOC>, RefreshInputError<I2CT>> {
         self.change_mode(Mode::Output).await?;
 
-        let mut pin = Pin {
-            expander: self.expander,
-            bank: self.bank,
-            id: self.id,
-            mode: PhantomData,
-            access_mode: PhantomData,
-            reset: PhantomData
-        };
+        let mut pin = self.convert();
 
-        let _ = pin.set_state_async(state).await;
+        pin.set_state_async(state).await?;
         pin.update_bank().await?;
         Ok(pin)
     }
+
+    /// Switches to regular access mode, the state is now updated synchronously
+    /// Cached output changes, which are not updated yet, are written with the next state change of the same bank.
+    pub fn into_regular_mode(self) -> Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC> {
+        self.convert()
+    }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> RefreshableInputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> RefreshableInputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     type Error = RefreshInputError<I2CT>;
 
     /// Refreshes the input state of all pins of the same bank
     async fn refresh_bank(&self) -> Result<(), Self::Error> {
-        self.refresh(self.bank).await
+        self.refresh(self.bank()).await
     }
 
     /// Refreshes the input state of all pins (on all banks)
@@ -107,17 +103,18 @@ where
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> RefreshableOutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> RefreshableOutputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-    type Error = I2CT::Error;
+    type Error = RefreshInputError<I2CT>;
 
     /// Updates the output state of all pins of the same bank
     async fn update_bank(&self) -> Result<(), Self::Error> {
-        self.update(self.bank).await
+        self.update(self.bank()).await
     }
 
     /// Updates the output state of all pins (on all banks)
@@ -127,48 +124,52 @@ where
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     /// Writes the output state of the given bank
-    async fn update(&self, bank: Bank) -> Result<(), I2CT::Error> {
-        let mut expander = self.expander.lock().await;
-        expander.as_mut().unwrap().write_output_state(bank).await
+    async fn update(&self, bank: Bank) -> Result<(), RefreshInputError<I2CT>> {
+        let mut expander = self.acquire().await?;
+        expander.write_output_state(bank).await.map_err(|e| expander.bus_error(e))
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> digital_hal_async::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: PinMode, WAIT, LOC: PinLocation> digital_hal_async::ErrorType
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-    type Error = Infallible;
+    type Error = RefreshInputError<I2CT>;
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> digital::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex,
+/// Blocking impls are acting on the cached state, so they just fail with [WouldBlock](RefreshInputError::WouldBlock)
+/// if the expander is locked by another task
+impl<'a, I2CT, RESET, LOCK, MODE: PinMode, WAIT, LOC: PinLocation> digital::ErrorType
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-    type Error = Infallible;
+    type Error = RefreshInputError<I2CT>;
 }
 
-
-impl<'a, I2CT, RESET, RAWMUTEX> InputPinAsync for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> InputPinAsync
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-
     async fn is_high_async(&mut self) -> Result<bool, Self::Error> {
-        let mut expander = self.expander.lock().await;
-        Ok(expander.as_mut().unwrap().is_pin_input_high(self.bank, self.id))
+        let expander = self.acquire().await?;
+        Ok(expander.is_pin_input_high(self.bank(), self.id()))
     }
 
     async fn is_low_async(&mut self) -> Result<bool, Self::Error> {
@@ -176,15 +177,15 @@ where
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> InputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex,
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> InputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-
     fn is_high(&mut self) -> Result<bool, Self::Error> {
-        embassy_futures::block_on(self.is_high_async())
+        Ok(self.try_acquire()?.is_pin_input_high(self.bank(), self.id()))
     }
 
     fn is_low(&mut self) -> Result<bool, Self::Error> {
@@ -192,29 +193,72 @@ impl<'a, I2CT, RESET, RAWMUTEX> InputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Inpu
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> digital_hal_async::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
+/// Bus errors are ignored while waiting, as they may be transient. The cached state is kept in this case and the
+/// refresh is repeated on the next wake-up. Fails if the lock timed out or the device was marked absent.
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> Wait
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    WAIT: WaitStrategy,
 {
-    type Error = Infallible;
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_ignoring_errors(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_ignoring_errors(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_ignoring_errors(false).await?;
+        self.wait_ignoring_errors(true).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_ignoring_errors(true).await?;
+        self.wait_ignoring_errors(false).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        match self.refresh_all().await {
+            Err(error) if !error.is_bus_error() => return Err(error),
+            _ => {}
+        }
+
+        let is_high = self.acquire().await?.is_pin_input_high(self.bank(), self.id());
+        self.wait_ignoring_errors(!is_high).await
+    }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> digital::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex,
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    WAIT: WaitStrategy,
 {
-    type Error = Infallible;
+    /// Waits until the input reaches the given state, retrying after bus errors
+    /// Any other error is returned, as repeating would not help, e.g. if the expander is held by another task or
+    /// the device was marked absent.
+    async fn wait_ignoring_errors(&self, is_high: bool) -> Result<(), RefreshInputError<I2CT>> {
+        loop {
+            match self.wait_for_state(is_high).await {
+                Err(error) if error.is_bus_error() => self.wait.wait_for_change().await,
+                result => return result,
+            }
+        }
+    }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> OutputPinAsync for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> OutputPinAsync
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     async fn set_low_async(&mut self) -> Result<(), Self::Error> {
         self.set_state_async(PinState::Low).await
@@ -225,16 +269,18 @@ where
     }
 
     async fn set_state_async(&mut self, state: PinState) -> Result<(), Self::Error> {
-        let mut expander = self.expander.lock().await;
-        Ok(expander.as_mut().unwrap().set_state(self.bank, self.id, state == PinState::High))
+        let mut expander = self.expander.lock_timeout().await?;
+        expander.set_state(self.bank(), self.id(), state == PinState::High);
+        Ok(())
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> OutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex,
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> OutputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     fn set_low(&mut self) -> Result<(), Self::Error> {
         self.set_state(PinState::Low)
@@ -245,21 +291,23 @@ impl<'a, I2CT, RESET, RAWMUTEX> OutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Out
     }
 
     fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
-        embassy_futures::block_on(self.set_state_async(state))
+        self.try_lock()?.set_state(self.bank(), self.id(), state == PinState::High);
+        Ok(())
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> StatefulOutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RefreshMode>
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> StatefulOutputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     fn is_set_high(&mut self) -> Result<bool, Self::Error> {
-        Ok(embassy_futures::block_on(self.is_pin_output_high()))
+        Ok(self.is_pin_output_high()?)
     }
 
     fn is_set_low(&mut self) -> Result<bool, Self::Error> {
         Ok(!self.is_set_high()?)
     }
-}
\ No newline at end of file
+}
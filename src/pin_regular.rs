@@ -1,92 +1,117 @@
-use crate::expander::{Bank, Mode, PCA9539, PinID, RefreshInputError};
-use crate::pins::{Input, Output, PinMode, Pin, RegularAccessMode};
-use core::marker::PhantomData;
-use embedded_hal_async::i2c::I2c;
-use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
-use embassy_sync::mutex::Mutex;
-use embassy_sync::blocking_mutex::raw::RawMutex;
-use crate::digital_hal_async::{InputPinAsync, OutputPinAsync};
 use crate::digital_hal_async;
+use crate::digital_hal_async::{InputPinAsync, OutputPinAsync};
+use crate::expander::{Mode, RefreshInputError};
+use crate::lock::ExpanderLock;
+use crate::pins::{
+    Input, InputMode, OpenDrain, Output, OutputMode, Pin, PinLocation, PinMode, RefreshMode, RegularAccessMode,
+};
+use crate::wait::WaitStrategy;
+use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
 
-impl<'a, I2CT, RESET, RAWMUTEX> Pin<'a, I2CT, RESET, RAWMUTEX, Input, RegularAccessMode>
+impl<'a, I2CT, RESET, LOCK, MODE, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex
+    LOCK: ExpanderLock<I2CT, RESET>,
+    MODE: PinMode,
 {
-    pub fn regular(expander: &'a Mutex<RAWMUTEX, Option<PCA9539<I2CT, RESET>>>, bank: Bank, id: PinID) -> Self {
-        Pin {
-            expander,
-            mode: PhantomData,
-            access_mode: PhantomData,
-            reset: PhantomData,
-            bank,
-            id,
-        }
+    pub async fn into_input_pin(
+        self,
+    ) -> Result<Pin<'a, I2CT, RESET, LOCK, Input, RegularAccessMode, WAIT, LOC>, RefreshInputError<I2CT>> {
+        self.change_mode(Mode::Input).await?;
+
+        Ok(self.convert())
     }
 
-    pub async fn into_input_pin(self) -> Result<Pin<'a, I2CT, RESET, RAWMUTEX, Input, RegularAccessMode>, I2CT::Error> {
-        self.change_mode(Mode::Input).await?;
+    pub async fn into_output_pin(
+        self,
+        state: PinState,
+    ) -> Result<Pin<'a, I2CT, RESET, LOCK, Output, RegularAccessMode, WAIT, LOC>, RefreshInputError<I2CT>> {
+        self.change_mode(Mode::Output).await?;
 
-        Ok(Pin {
-            expander: self.expander,
-            bank: self.bank,
-            id: self.id,
-            mode: PhantomData,
-            access_mode: PhantomData,
-            reset: PhantomData
-        })
-    }
-
-    pub async fn into_output_pin(self, state: PinState) -> Result<Pin<'a, I2CT, RESET, RAWMUTEX, Output, RegularAccessMode>, RefreshInputError<I2CT>> {
-        self.change_mode(Mode::Output).await.map_err(|e| RefreshInputError::<I2CT>::WriteError(e));
-
-        let mut pin = Pin {
-            expander: self.expander,
-            bank: self.bank,
-            id: self.id,
-            mode: PhantomData,
-            access_mode: PhantomData,
-            reset: PhantomData
-        };
+        let mut pin = self.convert();
 
         pin.set_state_async(state).await?;
         Ok(pin)
     }
+
+    /// Converts into an emulated open-drain pin, see [OpenDrainPin](crate::pins::OpenDrainPin)
+    /// The output latch is set to low, while the pin is in input mode, so the line is never glitched. When
+    /// releasing the line, the pin is switched to input first, otherwise the latch is cleared first.
+    pub async fn into_open_drain_pin(
+        self,
+        state: PinState,
+    ) -> Result<Pin<'a, I2CT, RESET, LOCK, OpenDrain, RegularAccessMode, WAIT, LOC>, RefreshInputError<I2CT>> {
+        {
+            let mut expander = self.acquire().await?;
+
+            if state == PinState::High {
+                expander
+                    .set_mode(self.bank(), self.id(), Mode::Input)
+                    .await
+                    .map_err(|e| expander.bus_error(e))?;
+            }
+
+            expander.set_state(self.bank(), self.id(), false);
+            expander
+                .write_output_state(self.bank())
+                .await
+                .map_err(|e| expander.bus_error(e))?;
+
+            if state == PinState::Low {
+                expander
+                    .set_mode(self.bank(), self.id(), Mode::Output)
+                    .await
+                    .map_err(|e| expander.bus_error(e))?;
+            }
+        }
+
+        Ok(self.convert())
+    }
+
+    /// Switches to refresh mode, the state is now just updated explicitly
+    /// The cached input state may be outdated, so refreshing is recommended before the first read.
+    pub fn into_refresh_mode(self) -> Pin<'a, I2CT, RESET, LOCK, MODE, RefreshMode, WAIT, LOC> {
+        self.convert()
+    }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX, MODE> digital_hal_async::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, MODE, RegularAccessMode>
+impl<'a, I2CT, RESET, LOCK, MODE, WAIT, LOC: PinLocation> digital_hal_async::ErrorType
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
-    MODE: PinMode
+    LOCK: ExpanderLock<I2CT, RESET>,
+    MODE: PinMode,
 {
     type Error = RefreshInputError<I2CT>;
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX, MODE> embedded_hal::digital::ErrorType for Pin<'a, I2CT, RESET, RAWMUTEX, MODE, RegularAccessMode>
+impl<'a, I2CT, RESET, LOCK, MODE, WAIT, LOC: PinLocation> embedded_hal::digital::ErrorType
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
-    MODE: PinMode
+    LOCK: ExpanderLock<I2CT, RESET>,
+    MODE: PinMode,
 {
     type Error = RefreshInputError<I2CT>;
 }
 
-
-impl<'a, I2CT, RESET, RAWMUTEX> InputPinAsync for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RegularAccessMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> InputPinAsync
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-
     async fn is_high_async(&mut self) -> Result<bool, Self::Error> {
-        let mut expander = self.expander.lock().await;
-        expander.as_mut().unwrap().refresh_input_state(self.bank).await?;
-        Ok(expander.as_mut().unwrap().is_pin_input_high(self.bank, self.id))
+        let mut expander = self.acquire().await?;
+        expander.refresh_input_state(self.bank()).await?;
+        Ok(expander.is_pin_input_high(self.bank(), self.id()))
     }
 
     async fn is_low_async(&mut self) -> Result<bool, Self::Error> {
@@ -94,13 +119,71 @@ impl<'a, I2CT, RESET, RAWMUTEX> InputPinAsync for Pin<'a, I2CT, RESET, RAWMUTEX,
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> OutputPinAsync for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RegularAccessMode>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> Wait
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    WAIT: WaitStrategy,
+{
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(false).await?;
+        self.wait_for_state(true).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(true).await?;
+        self.wait_for_state(false).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let is_high = self.is_high_async().await?;
+        self.wait_for_state(!is_high).await
+    }
+}
+
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> OutputPinAsync
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
+    async fn set_low_async(&mut self) -> Result<(), Self::Error> {
+        self.set_state_async(PinState::Low).await
+    }
+
+    async fn set_high_async(&mut self) -> Result<(), Self::Error> {
+        self.set_state_async(PinState::High).await
+    }
+
+    async fn set_state_async(&mut self, state: PinState) -> Result<(), Self::Error> {
+        let mut expander = self.acquire().await?;
+        expander.set_state(self.bank(), self.id(), state == PinState::High);
+        expander
+            .write_output_state(self.bank())
+            .await
+            .map_err(|e| expander.bus_error(e))
+    }
+}
 
+/// Drives low by switching to output mode, releases the line by switching to input mode
+impl<'a, I2CT, RESET, LOCK, WAIT, LOC: PinLocation> OutputPinAsync
+    for Pin<'a, I2CT, RESET, LOCK, OpenDrain, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
     async fn set_low_async(&mut self) -> Result<(), Self::Error> {
         self.set_state_async(PinState::Low).await
     }
@@ -110,20 +193,59 @@ where
     }
 
     async fn set_state_async(&mut self, state: PinState) -> Result<(), Self::Error> {
-        let mut expander = self.expander.lock().await;
-        expander.as_mut().unwrap().set_state(self.bank, self.id, state == PinState::High);
-        expander.as_mut().unwrap().write_output_state(self.bank).await.map_err(|e| RefreshInputError::I2cError(e))
+        let mode = match state {
+            PinState::Low => Mode::Output,
+            PinState::High => Mode::Input,
+        };
+
+        let mut expander = self.acquire().await?;
+        expander
+            .set_mode(self.bank(), self.id(), mode)
+            .await
+            .map_err(|e| expander.bus_error(e))
+    }
+}
+
+impl<'a, I2CT, RESET, LOCK, WAIT, LOC: PinLocation> OutputPin
+    for Pin<'a, I2CT, RESET, LOCK, OpenDrain, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_state(PinState::Low)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_state(PinState::High)
+    }
+
+    fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
+        let mode = match state {
+            PinState::Low => Mode::Output,
+            PinState::High => Mode::Input,
+        };
+
+        let mut expander = self.try_acquire()?;
+        embassy_futures::block_on(expander.set_mode(self.bank(), self.id(), mode))
+            .map_err(|e| expander.bus_error(e))
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> InputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Input, RegularAccessMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex
+/// Blocking impls fail with [WouldBlock](RefreshInputError::WouldBlock) if the expander is locked, instead of
+/// waiting for other tasks. Bus transfers are still driven by the async bus.
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, WAIT, LOC: PinLocation> InputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     fn is_high(&mut self) -> Result<bool, Self::Error> {
-        embassy_futures::block_on(self.is_high_async())
+        let mut expander = self.try_acquire()?;
+        embassy_futures::block_on(expander.refresh_input_state(self.bank()))?;
+        Ok(expander.is_pin_input_high(self.bank(), self.id()))
     }
 
     fn is_low(&mut self) -> Result<bool, Self::Error> {
@@ -131,38 +253,42 @@ impl<'a, I2CT, RESET, RAWMUTEX> InputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Inpu
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> OutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RegularAccessMode>
-    where
-        I2CT: I2c,
-        RESET: OutputPin,
-        RAWMUTEX: RawMutex
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> OutputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        embassy_futures::block_on(self.set_low_async())
+        self.set_state(PinState::Low)
     }
 
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        embassy_futures::block_on(self.set_high_async())
+        self.set_state(PinState::High)
     }
 
     fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
-        embassy_futures::block_on(self.set_state_async(state))
+        let mut expander = self.try_acquire()?;
+        expander.set_state(self.bank(), self.id(), state == PinState::High);
+        embassy_futures::block_on(expander.write_output_state(self.bank())).map_err(|e| expander.bus_error(e))
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX> StatefulOutputPin for Pin<'a, I2CT, RESET, RAWMUTEX, Output, RegularAccessMode>
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, WAIT, LOC: PinLocation> StatefulOutputPin
+    for Pin<'a, I2CT, RESET, LOCK, MODE, RegularAccessMode, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex
+    LOCK: ExpanderLock<I2CT, RESET>,
 {
-    /// As this is just acting on cached register data, its in fact Infallible
+    /// Acting on cached register data, so just fails if the expander is locked
     fn is_set_high(&mut self) -> Result<bool, Self::Error> {
-        Ok(embassy_futures::block_on(self.is_pin_output_high()))
+        Ok(self.is_pin_output_high()?)
     }
 
-    /// As this is just acting on cached register data, its in fact Infallible
+    /// Acting on cached register data, so just fails if the expander is locked
     fn is_set_low(&mut self) -> Result<bool, Self::Error> {
         Ok(!self.is_set_high()?)
     }
-}
\ No newline at end of file
+}
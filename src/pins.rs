@@ -1,49 +1,67 @@
 //! # Individual GPIO pins
 //!
-//! This crate fully implements the [digital::v2 traits of embedded_hal](https://docs.rs/embedded-hal/latest/embedded_hal/digital/v2/index.html).
+//! This crate fully implements the [digital traits of embedded_hal](https://docs.rs/embedded-hal/latest/embedded_hal/digital/index.html).
 //!
 //! Due to the I2C overhead, this module offers two options for state management:
 //! * [Regular access mode](RegularAccessMode): The state is synchronously updated when calling
-//! state functions like `is_high()`, causing 1:1 I2C operations for each individual call.
+//!   state functions like `is_high()`, causing 1:1 I2C operations for each individual call.
 //! * [Refresh access mode](RefreshMode): Register states are internally cached. Functions like
-//! `is_high()` are just using the cached state. The state is updated explicitly, but for all pins at once.
-//! In the best case, the I2C overhead is reduced to one eighth. See [below examples](#refreshable-access-mode) for more details.
+//!   `is_high()` are just using the cached state. The state is updated explicitly, but for all pins at once.
+//!   In the best case, the I2C overhead is reduced to one eighth. See [below examples](#refreshable-access-mode) for more details.
 //!
 //! ## Setup
-//! Individual pins can be fetched using [PCA9539](crate::expander::PCA9539) instance.
+//! Individual pins can be fetched using a [PCA9539](crate::expander::PCA9539) instance, which is shared by a lock.
 //! Different concurrency models are supported, see [Concurrency](#Concurrency) section for more details.
+//! The lock holds the expander directly, so pins never act on an uninitialized expander. If the expander
+//! is set up later at runtime, the whole lock is initialized lazily instead (e.g. using `static_cell`).
+//!
+//! Each pin is handed out just once at a time, fetching a pin in use returns None. Dropping the pin frees it again.
 //! ```
-//! use pca9539::example::DummyI2CBus;
-//! use pca9539::expander::Bank::Bank0;
+//! use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//! use embassy_sync::mutex::Mutex;
+//! use pca9539::example::{DummyI2CBus, DummyResetPin};
 //! use pca9539::expander::PCA9539;
-//! use pca9539::expander::PinID::Pin1;
+//! use pca9539::pins::Pins;
 //!
+//!# embassy_futures::block_on(async {
 //! let i2c_bus = DummyI2CBus::default();
-//! let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
+//! let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//! let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//!# });
 //! ```
 //! ## State management modes
+//! Pins are switched between both modes using `into_refresh_mode()` and `into_regular_mode()`. Input and output
+//! mode are switched using `into_input_pin()` and `into_output_pin()`, regardless of the current mode.
+//! Bidirectional lines are handled by a [FlexPin], which direction is switched at runtime.
+//! Open-drain outputs for shared lines are emulated by an [OpenDrainPin].
 //! ### Regular access mode
 //! The following examples demonstrate using the synchronous regular access mode.
 //! Regular access mode is used when calling `get_pin()` method.
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::{Bank0, Bank1};
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::{Pin1, Pin2, Pin4};
-//!# use embedded_hal::digital::v2::{InputPin, IoPin, PinState, OutputPin};
+//!# use pca9539::pins::Pins;
+//!# use embedded_hal::digital::{InputPin, PinState, OutputPin};
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
-//! let pin12 = pins.get_pin(Bank1, Pin2);
-//! let mut  pin04 = pins.get_pin(Bank0, Pin4).into_output_pin(PinState::Low).unwrap();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let mut pin12 = pins.get_pin(Bank1, Pin2).unwrap();
+//! let mut pin04 = pins.get_pin(Bank0, Pin4).unwrap().into_output_pin(PinState::Low).await.unwrap();
 //!
 //! // Fetching input state of Pin12
 //! let is_high = pin12.is_high().unwrap();
 //!
 //! // Setting Pin04 to high output state
 //! pin04.set_high().unwrap()
+//!# });
 //! ```
 //! ### Refreshable access mode
 //! The following examples demonstrate using the refreshable access mode.
@@ -53,145 +71,385 @@
 //! It does not matter which pin is used to call update/refresh.
 //! The state is always updated for all pins or pins of the same bank.
 //!
-//! As `is_high()` and `is_low()` are just acting on cached state, calls of this method can not fail.
+//! As `is_high()` and `is_low()` are just acting on cached state, calls of this method can not fail due to bus errors.
+//!
+//! The blocking [embedded_hal] impls of both modes never wait for the expander. If it is locked by another task,
+//! [WouldBlock] is returned instead of blocking the executor forever.
 //! #### Input example
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::{Bank0, Bank1};
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::{Pin0, Pin1, Pin2, Pin3, Pin4};
-//!# use embedded_hal::digital::v2::{InputPin, IoPin, PinState, OutputPin};
-//!# use pca9539::pins::RefreshableInputPin;
+//!# use pca9539::pins::{Pins, RefreshableInputPin};
+//!# use embedded_hal::digital::InputPin;
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
-//! let pin00 = pins.get_refreshable_pin(Bank0, Pin0);
-//! let pin10 = pins.get_refreshable_pin(Bank1, Pin0);
-//! let pin11 = pins.get_refreshable_pin(Bank1, Pin1);
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).unwrap();
+//! let mut pin10 = pins.get_refreshable_pin(Bank1, Pin0).unwrap();
+//! let mut pin11 = pins.get_refreshable_pin(Bank1, Pin1).unwrap();
 //!
 //! // Updates the input state of just Bank1. So input state of Pin10 and Pin11 is now up2date
-//! pin10.refresh_bank().unwrap();
+//! pin10.refresh_bank().await.unwrap();
 //! assert!(pin10.is_high().unwrap());
 //! assert!(pin11.is_low().unwrap());
 //!
 //! // Updates the input state of all banks. So all pins are now up2date
-//! pin00.refresh_bank().unwrap();
+//! pin00.refresh_all().await.unwrap();
 //! assert!(pin00.is_low().unwrap());
+//!# });
 //! ```
 //! #### Output example
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::Bank::{Bank0, Bank1};
 //!# use pca9539::expander::PCA9539;
 //!# use pca9539::expander::PinID::{Pin0, Pin1, Pin2, Pin3, Pin4};
-//!# use embedded_hal::digital::v2::{InputPin, IoPin, PinState, OutputPin};
-//!# use pca9539::pins::RefreshableOutputPin;
+//!# use pca9539::pins::{Pins, RefreshableOutputPin};
+//!# use embedded_hal::digital::{PinState, OutputPin};
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
-//! let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
-//! let mut pin10 = pins.get_refreshable_pin(Bank1, Pin0).into_output_pin(PinState::Low).unwrap();
-//! let mut pin11 = pins.get_refreshable_pin(Bank1, Pin1).into_output_pin(PinState::Low).unwrap();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::Low).await.unwrap();
+//! let mut pin10 = pins.get_refreshable_pin(Bank1, Pin0).unwrap().into_output_pin(PinState::Low).await.unwrap();
+//! let mut pin11 = pins.get_refreshable_pin(Bank1, Pin1).unwrap().into_output_pin(PinState::Low).await.unwrap();
 //!
 //! pin00.set_low().unwrap();
 //! pin10.set_high().unwrap();
 //! pin11.set_state(PinState::High).unwrap();
 //!
 //! // Writes the output state of just Bank1.
-//! pin10.update_bank().unwrap();
+//! pin10.update_bank().await.unwrap();
 //!
 //! // Writes the output state of all banks.
-//! pin00.update_all().unwrap();
+//! pin00.update_all().await.unwrap();
+//!# });
 //! ```
 //!
-//! ## Concurrency
-//! As the pins are using a shared reference, some kind of concurrency management is required.
-//! This crate currently offers three different concurrency guards. Which one should be used, depends
-//! on the application type:
+//! ## Waiting for input changes
+//! Input pins of both access modes implement [Wait](embedded_hal_async::digital::Wait), if the pins
+//! container is created using a [wait strategy](crate::wait). On each wake-up of the strategy, the
+//! input state of both banks is refreshed and evaluated:
+//! * [InterruptWait](crate::wait::InterruptWait): Waits on the INT output of PCA9539
+//! * [PollingWait](crate::wait::PollingWait): Polling fallback for boards without routed INT line
 //!
-//! ### Lock-free
-//! Returns a pins container without using any locks
-//! This is the most efficient way of using individual pins
-//! The downside is, that these pins are neither Send or Sync, so can only be used in single-threaded
-//! and interrupt-free applications
+//! Refresh mode pins ignore bus errors while waiting and repeat the refresh on the next wake-up.
+//! Lock timeouts and absent devices are still reported.
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyDelay, DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
 //!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!# use pca9539::pins::Pins;
+//!# use pca9539::wait::PollingWait;
+//!# use embedded_hal_async::digital::Wait;
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//! let pins = expander.pins();
-//! ```
-//!
-//! ### CS Mutex (Cortex-M)
-//! Returns a pins container using Mutex based on critical sections
-//! Individual pins can be used across threads and interrupts, as long just running on a single core
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! // Polling the input state every 10 ms
+//! let wait = PollingWait::new(DummyDelay::default(), 10_000);
+//! let pins = Pins::with_wait(&expander, &wait);
 //!
-//! *Requires activation of `cortex-m` feature*
+//! let mut pin01 = pins.get_pin(Bank0, Pin1).unwrap();
+//! pin01.wait_for_high().await.unwrap();
+//!# });
+//! ```
 //!
+//! ## Typed pins
+//! [Pins::split()] returns all pins at once, each pin as individually typed field. Bank and ID are fixed at
+//! compile time, so pins can't be mixed up.
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
 //!# use pca9539::expander::PCA9539;
+//!# use pca9539::pins::Pins;
+//!# use embedded_hal::digital::{InputPin, OutputPin, PinState};
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//!# #[cfg(feature = "cortex-m")]
-//! let pins = expander.pins_cs_mutex();
-//! ```
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! let mut parts = pins.split().unwrap();
 //!
-//! ### Spin Mutex
-//! Returns a pins container using a spin mutex
-//! This is safe to use across threads and on multi-core applications
-//! However, this requires a system supporting spin mutexes, which are generally only
-//! available on systems with Atomic CAS
+//! assert!(parts.p0_1.is_high().unwrap());
 //!
-//! *Requires activation of `spin` feature*
+//! let mut led = parts.p1_7.into_output_pin(PinState::Low).await.unwrap();
+//! led.set_high().unwrap();
+//!# });
+//! ```
 //!
+//! ## Teardown
+//! Once all pins are dropped, the container is torn down by [Pins::release], which returns exclusive access to
+//! the expander. This works for static locks as well, e.g. for temporarily handing the bus to another routine.
+//! Bus and reset pin are recovered by consuming the lock using `into_inner()` (e.g. [Mutex::into_inner()](embassy_sync::mutex::Mutex::into_inner))
+//! and releasing the expander.
 //! ```
-//!# use pca9539::example::DummyI2CBus;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
 //!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!# use pca9539::pins::Pins;
 //!#
+//!# embassy_futures::block_on(async {
 //!# let i2c_bus = DummyI2CBus::default();
-//!# let mut  expander = PCA9539::new(i2c_bus, 0x74);
-//!# #[cfg(feature = "spin")]
-//! let pins = expander.pins_spin_mutex();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! let pins = Pins::new(&expander);
+//! {
+//!     let pin01 = pins.get_pin(Bank0, Pin1).unwrap();
+//! }
+//!
+//! let mut guard = pins.release().await.ok().unwrap();
+//! let i2c_bus = guard.bus_mut();
+//!# drop(guard);
+//!
+//! let (i2c_bus, reset_pin) = expander.into_inner().release();
+//!# });
 //! ```
-use crate::expander::{Bank, Mode, PCA9539, PinID};
-use crate::guard::RefGuard;
+//!
+//! ## Concurrency
+//! As the pins are using a shared reference, the expander is wrapped by a lock implementing
+//! [ExpanderLock]. The lock defines the concurrency model and depends on the application type:
+//! * [RefCell](core::cell::RefCell): Most efficient, but the pins are neither Send or Sync, so can only be used
+//!   within a single executor.
+//! * [Mutex](embassy_sync::mutex::Mutex): Async mutex of embassy. The [RawMutex] defines whether pins are
+//!   usable across executors and interrupts, e.g. [CriticalSectionRawMutex](embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex).
+//! * `CsMutex`: Mutex based on critical sections, pins can be used across executors and interrupts.
+//!   Requires feature `critical-section`.
+//! * `spin::Mutex`: Safe to use across threads and on multi-core applications, but requires a system supporting
+//!   atomic CAS. Requires feature `spin`.
+use crate::diagnostics::Diagnostics;
+use crate::expander::{Bank, Mode, PinID, RefreshInputError, PIN_IDS};
+use crate::lock::{ExpanderLock, WouldBlock};
+use crate::timeout::Timeout;
+use crate::wait::{NoWait, WaitStrategy};
+use core::cell::Cell;
 use core::marker::PhantomData;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embedded_hal::digital::OutputPin;
 use embedded_hal_async::i2c::I2c;
-use embassy_sync::mutex::Mutex;
-use embassy_sync::blocking_mutex::raw::RawMutex;
 
 pub use crate::pin_refreshable::{RefreshableInputPin, RefreshableOutputPin};
 
 /// Container for fetching individual pins
-pub struct Pins<'a, I2CT: I2c, RESET: OutputPin, RAWMUTEX: RawMutex> {
-    expander: &'a Mutex<RAWMUTEX, Option<PCA9539<I2CT, RESET>>>
+/// Each pin is just handed out once at a time. Pins are freed again when dropped.
+pub struct Pins<'a, I2CT: I2c, RESET: OutputPin, LOCK: ExpanderLock<I2CT, RESET>, WAIT = NoWait> {
+    expander: &'a LOCK,
+    wait: &'a WAIT,
+    taken: TakenMask<LOCK::RawMutex>,
 }
 
-impl<'a, I2CT: I2c, RESET: OutputPin, RAWMUTEX: RawMutex> Pins<'a, I2CT, RESET, RAWMUTEX> {
-    pub fn new(expander: &'a Mutex<RAWMUTEX, Option<PCA9539<I2CT, RESET>>>) -> Self {
+impl<'a, I2CT: I2c, RESET: OutputPin, LOCK: ExpanderLock<I2CT, RESET>> Pins<'a, I2CT, RESET, LOCK> {
+    pub fn new(expander: &'a LOCK) -> Self {
         Self {
-            expander
+            expander,
+            wait: &NoWait {},
+            taken: TakenMask::new(),
         }
     }
+}
 
+impl<'a, I2CT: I2c, RESET: OutputPin, LOCK: ExpanderLock<I2CT, RESET>, WAIT: WaitStrategy>
+    Pins<'a, I2CT, RESET, LOCK, WAIT>
+{
+    /// Returns a pins container, which input pins are implementing [Wait](embedded_hal_async::digital::Wait)
+    /// based on the given strategy
+    pub fn with_wait(expander: &'a LOCK, wait: &'a WAIT) -> Self {
+        Self {
+            expander,
+            wait,
+            taken: TakenMask::new(),
+        }
+    }
+}
+
+impl<'a, I2CT: I2c, RESET: OutputPin, LOCK: ExpanderLock<I2CT, RESET>, WAIT> Pins<'a, I2CT, RESET, LOCK, WAIT> {
     /// Returns an individual pin, which state gets updated synchronously
-    /// **The library does not prevent multiple parallel instances of the same pin.**
-    pub fn get_pin(&self, bank: Bank, id: PinID) -> Pin<'a, I2CT, RESET, RAWMUTEX, Input, RegularAccessMode> {
-        Pin::regular(self.expander, bank, id)
+    /// Returns None if the pin is already in use.
+    pub fn get_pin(
+        &self,
+        bank: Bank,
+        id: PinID,
+    ) -> Option<Pin<'_, I2CT, RESET, LOCK, Input, RegularAccessMode, WAIT>> {
+        Pin::claim(self.expander, self.wait, &self.taken, DynLocation { bank, id })
     }
 
     /// Returns an individual pin, which is using a cached state
     /// The status is explicitly updated. This allows a more efficient status query and assignment,
     /// as the status is only updated once for all pins.
-    /// **The library does not prevent multiple parallel instances of the same pin.**
-    pub fn get_refreshable_pin(&self, bank: Bank, id: PinID) -> Pin<'a, I2CT, RESET, RAWMUTEX, Input, RefreshMode> {
-        Pin::refreshable(self.expander, bank, id)
+    /// Returns None if the pin is already in use.
+    pub fn get_refreshable_pin(
+        &self,
+        bank: Bank,
+        id: PinID,
+    ) -> Option<Pin<'_, I2CT, RESET, LOCK, Input, RefreshMode, WAIT>> {
+        Pin::claim(self.expander, self.wait, &self.taken, DynLocation { bank, id })
+    }
+
+    /// Returns all pins as individually typed fields, with bank and ID fixed at compile time
+    /// Returns None if any pin is already in use.
+    pub fn split(&self) -> Option<Parts<'_, I2CT, RESET, LOCK, WAIT>> {
+        if !self.taken.claim_all() {
+            return None;
+        }
+
+        Some(Parts {
+            p0_0: self.typed(),
+            p0_1: self.typed(),
+            p0_2: self.typed(),
+            p0_3: self.typed(),
+            p0_4: self.typed(),
+            p0_5: self.typed(),
+            p0_6: self.typed(),
+            p0_7: self.typed(),
+            p1_0: self.typed(),
+            p1_1: self.typed(),
+            p1_2: self.typed(),
+            p1_3: self.typed(),
+            p1_4: self.typed(),
+            p1_5: self.typed(),
+            p1_6: self.typed(),
+            p1_7: self.typed(),
+        })
+    }
+
+    /// Returns true if the given pin is currently in use
+    pub fn is_taken(&self, bank: Bank, id: PinID) -> bool {
+        self.taken.is_taken(bank, id)
+    }
+
+    /// Tears down the container and returns exclusive access to the expander, see [Teardown](#teardown)
+    /// Fails by returning the container, if any pin is still taken, e.g. leaked by [core::mem::forget].
+    pub async fn release(self) -> Result<LOCK::Guard<'a>, Self> {
+        if !self.taken.claim_all() {
+            return Err(self);
+        }
+
+        Ok(self.expander.lock().await)
+    }
+
+    /// Returns the diagnostic counters of the expander
+    /// Fails fast with [WouldBlock] if the expander is locked by another task.
+    pub fn diagnostics(&self) -> Result<Diagnostics, WouldBlock> {
+        Ok(self.expander.try_lock().ok_or(WouldBlock {})?.diagnostics())
+    }
+
+    /// Returns the diagnostic counters of the expander, waiting until the expander is available
+    /// Fails with [Timeout] if the lock timed out.
+    pub async fn diagnostics_async(&self) -> Result<Diagnostics, Timeout> {
+        Ok(self.expander.lock_timeout().await?.diagnostics())
+    }
+
+    /// Returns the typed pin, which is already claimed
+    fn typed<const BANK: u8, const ID: u8>(&self) -> TypedPin<'_, I2CT, RESET, LOCK, BANK, ID, WAIT> {
+        Pin {
+            expander: self.expander,
+            wait: self.wait,
+            taken: &self.taken,
+            location: FixedLocation {},
+            mode: PhantomData,
+            access_mode: PhantomData,
+            reset: PhantomData,
+        }
+    }
+}
+
+/// Pin in regular access mode, which bank and ID are fixed at compile time
+pub type TypedPin<'a, I2CT, RESET, LOCK, const BANK: u8, const ID: u8, WAIT = NoWait> =
+    Pin<'a, I2CT, RESET, LOCK, Input, RegularAccessMode, WAIT, FixedLocation<BANK, ID>>;
+
+/// All pins of an expander, returned by [Pins::split()]
+/// Field `pB_N` is pin N of bank B.
+pub struct Parts<'a, I2CT: I2c, RESET: OutputPin, LOCK: ExpanderLock<I2CT, RESET>, WAIT = NoWait> {
+    pub p0_0: TypedPin<'a, I2CT, RESET, LOCK, 0, 0, WAIT>,
+    pub p0_1: TypedPin<'a, I2CT, RESET, LOCK, 0, 1, WAIT>,
+    pub p0_2: TypedPin<'a, I2CT, RESET, LOCK, 0, 2, WAIT>,
+    pub p0_3: TypedPin<'a, I2CT, RESET, LOCK, 0, 3, WAIT>,
+    pub p0_4: TypedPin<'a, I2CT, RESET, LOCK, 0, 4, WAIT>,
+    pub p0_5: TypedPin<'a, I2CT, RESET, LOCK, 0, 5, WAIT>,
+    pub p0_6: TypedPin<'a, I2CT, RESET, LOCK, 0, 6, WAIT>,
+    pub p0_7: TypedPin<'a, I2CT, RESET, LOCK, 0, 7, WAIT>,
+    pub p1_0: TypedPin<'a, I2CT, RESET, LOCK, 1, 0, WAIT>,
+    pub p1_1: TypedPin<'a, I2CT, RESET, LOCK, 1, 1, WAIT>,
+    pub p1_2: TypedPin<'a, I2CT, RESET, LOCK, 1, 2, WAIT>,
+    pub p1_3: TypedPin<'a, I2CT, RESET, LOCK, 1, 3, WAIT>,
+    pub p1_4: TypedPin<'a, I2CT, RESET, LOCK, 1, 4, WAIT>,
+    pub p1_5: TypedPin<'a, I2CT, RESET, LOCK, 1, 5, WAIT>,
+    pub p1_6: TypedPin<'a, I2CT, RESET, LOCK, 1, 6, WAIT>,
+    pub p1_7: TypedPin<'a, I2CT, RESET, LOCK, 1, 7, WAIT>,
+}
+
+/// Tracks the pins currently handed out
+/// Bit `n` of the lower byte belongs to Bank0, bit `n` of the upper byte to Bank1.
+pub(crate) struct TakenMask<RAWMUTEX: RawMutex> {
+    mask: BlockingMutex<RAWMUTEX, Cell<u16>>,
+}
+
+impl<RAWMUTEX: RawMutex> TakenMask<RAWMUTEX> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            mask: BlockingMutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Marks the given pin as taken. Returns false if the pin was already taken.
+    pub(crate) fn claim(&self, bank: Bank, id: PinID) -> bool {
+        let bit = Self::bit(bank, id);
+
+        self.mask.lock(|mask| {
+            if mask.get() & bit != 0 {
+                return false;
+            }
+
+            mask.set(mask.get() | bit);
+            true
+        })
+    }
+
+    /// Marks all pins as taken. Returns false if any pin was already taken.
+    pub(crate) fn claim_all(&self) -> bool {
+        self.mask.lock(|mask| {
+            if mask.get() != 0 {
+                return false;
+            }
+
+            mask.set(u16::MAX);
+            true
+        })
+    }
+
+    /// Marks the given pin as free
+    pub(crate) fn free(&self, bank: Bank, id: PinID) {
+        let bit = Self::bit(bank, id);
+        self.mask.lock(|mask| mask.set(mask.get() & !bit));
+    }
+
+    pub(crate) fn is_taken(&self, bank: Bank, id: PinID) -> bool {
+        self.mask.lock(|mask| mask.get() & Self::bit(bank, id) != 0)
+    }
+
+    fn bit(bank: Bank, id: PinID) -> u16 {
+        1 << ((bank as u16) * 8 + id as u16)
     }
 }
 
@@ -200,7 +458,7 @@ impl<'a, I2CT: I2c, RESET: OutputPin, RAWMUTEX: RawMutex> Pins<'a, I2CT, RESET,
 /// Currently there are two modes supported:
 /// * Regular: State of the pin is synchronously fetched from I2C bus when calling functions like `is_high()`
 /// * Refreshable: State of all pins is refreshed explicitly and functions like `is_high()` are working on a cached state.
-/// This reducing the I2C overhead
+///   This reducing the I2C overhead
 pub trait AccessMode {}
 
 /// State of the pin is synchronously fetched from I2C bus
@@ -222,60 +480,296 @@ impl PinMode for Input {}
 pub struct Output {}
 impl PinMode for Output {}
 
+/// Direction is switched at runtime, see [FlexPin]
+pub struct Flex {}
+impl PinMode for Flex {}
+
+/// Emulated open-drain output, see [OpenDrainPin]
+pub struct OpenDrain {}
+impl PinMode for OpenDrain {}
+
+/// Modes supporting reading the input state
+pub trait InputMode: PinMode {}
+impl InputMode for Input {}
+impl InputMode for Flex {}
+impl InputMode for OpenDrain {}
+
+/// Modes supporting setting the output state
+pub trait OutputMode: PinMode {}
+impl OutputMode for Output {}
+impl OutputMode for Flex {}
+
+/// Open-drain output emulated on the push-pull outputs of the expander
+/// The output latch is kept at low. Driving low switches the pin to output, releasing the line (high) switches
+/// the pin back to input (high-Z). Reading returns the real line level, so wired-OR lines are supported.
+/// Just available in regular access mode, as each state change is a synchronous configuration write.
+pub type OpenDrainPin<'a, I2CT, RESET, LOCK, WAIT = NoWait, LOC = DynLocation> =
+    Pin<'a, I2CT, RESET, LOCK, OpenDrain, RegularAccessMode, WAIT, LOC>;
+
+/// Pin, which direction is switched at runtime using `set_as_input()` and `set_as_output()`
+/// Input and output state are accessible regardless of the current direction. While configured as input,
+/// the output state is just stored in the output register and driven after switching to output.
+pub type FlexPin<'a, I2CT, RESET, LOCK, ACCESS = RegularAccessMode, WAIT = NoWait, LOC = DynLocation> =
+    Pin<'a, I2CT, RESET, LOCK, Flex, ACCESS, WAIT, LOC>;
+
+/// Defines how bank and ID of a pin are stored
+pub trait PinLocation: Copy {
+    fn bank(&self) -> Bank;
+    fn id(&self) -> PinID;
+}
+
+/// Bank and ID are selected at runtime
+#[derive(Copy, Clone)]
+pub struct DynLocation {
+    pub(crate) bank: Bank,
+    pub(crate) id: PinID,
+}
+
+impl PinLocation for DynLocation {
+    fn bank(&self) -> Bank {
+        self.bank
+    }
+
+    fn id(&self) -> PinID {
+        self.id
+    }
+}
+
+/// Bank (0 or 1) and ID (0 to 7) are fixed at compile time
+#[derive(Copy, Clone)]
+pub struct FixedLocation<const BANK: u8, const ID: u8> {}
+
+impl<const BANK: u8, const ID: u8> PinLocation for FixedLocation<BANK, ID> {
+    fn bank(&self) -> Bank {
+        match BANK {
+            0 => Bank::Bank0,
+            _ => Bank::Bank1,
+        }
+    }
+
+    fn id(&self) -> PinID {
+        PIN_IDS[ID as usize]
+    }
+}
+
 /// Individual GPIO pin
-pub struct Pin<'a, I2CT, RESET, RAWMUTEX, MODE, ACCESS>
+pub struct Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT = NoWait, LOC = DynLocation>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
     MODE: PinMode,
     ACCESS: AccessMode,
+    LOC: PinLocation,
 {
-    pub(crate) expander: &'a Mutex<RAWMUTEX, Option<PCA9539<I2CT, RESET>>>,
-    pub(crate) bank: Bank,
-    pub(crate) id: PinID,
+    pub(crate) expander: &'a LOCK,
+    pub(crate) wait: &'a WAIT,
+    pub(crate) taken: &'a TakenMask<LOCK::RawMutex>,
+    pub(crate) location: LOC,
     pub(crate) mode: PhantomData<MODE>,
     pub(crate) access_mode: PhantomData<ACCESS>,
     pub(crate) reset: PhantomData<RESET>,
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX, ACCESS> Pin<'a, I2CT, RESET, RAWMUTEX, Input, ACCESS>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, ACCESS, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
     ACCESS: AccessMode,
 {
     /// Reverses/Resets the input polarity
-    pub async fn invert_polarity(&self, invert: bool) -> Result<(), I2CT::Error> {
-        self.expander.lock().await.as_mut().unwrap().reverse_polarity(self.bank, self.id, invert).await
+    pub async fn invert_polarity(&self, invert: bool) -> Result<(), RefreshInputError<I2CT>> {
+        let mut expander = self.acquire().await?;
+        expander
+            .reverse_polarity(self.bank(), self.id(), invert)
+            .await
+            .map_err(|e| expander.bus_error(e))
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX, ACCESS> Pin<'a, I2CT, RESET, RAWMUTEX, Output, ACCESS>
+impl<'a, I2CT, RESET, LOCK, MODE: InputMode, ACCESS, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    ACCESS: AccessMode,
+    WAIT: WaitStrategy,
+{
+    /// Waits until the input reaches the given state
+    /// The input state of both banks is refreshed on each wake-up, as INT is just released when
+    /// reading the input register causing the interrupt.
+    pub(crate) async fn wait_for_state(&self, is_high: bool) -> Result<(), RefreshInputError<I2CT>> {
+        loop {
+            {
+                let mut expander = self.acquire().await?;
+                expander.refresh_input_state(Bank::Bank0).await?;
+                expander.refresh_input_state(Bank::Bank1).await?;
+
+                if expander.is_pin_input_high(self.bank(), self.id()) == is_high {
+                    return Ok(());
+                }
+            }
+
+            self.wait.wait_for_change().await;
+        }
+    }
+}
+
+impl<'a, I2CT, RESET, LOCK, MODE: OutputMode, ACCESS, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
     ACCESS: AccessMode,
 {
     /// Returns the current output state, this logic is independent from access mode, as it acts in both
-    /// cases on cached register state
-    pub(crate) async fn is_pin_output_high(&self) -> bool {
-        self.expander.lock().await.as_mut().unwrap().is_pin_output_high(self.bank, self.id)
+    /// cases on cached register state. Fails if the expander is locked.
+    pub(crate) fn is_pin_output_high(&self) -> Result<bool, WouldBlock> {
+        Ok(self.try_lock()?.is_pin_output_high(self.bank(), self.id()))
     }
 }
 
-impl<'a, I2CT, RESET, RAWMUTEX, MODE, ACCESS> Pin<'a, I2CT, RESET, RAWMUTEX, MODE, ACCESS>
+impl<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
 where
     I2CT: I2c,
     RESET: OutputPin,
-    RAWMUTEX: RawMutex,
+    LOCK: ExpanderLock<I2CT, RESET>,
     ACCESS: AccessMode,
     MODE: PinMode,
 {
     /// Switches the pin to the given mode
-    pub(crate) async fn change_mode(&self, mode: Mode) -> Result<(), I2CT::Error> {
-        self.expander.lock().await.as_mut().unwrap().set_mode(self.bank, self.id, mode).await
+    pub(crate) async fn change_mode(&self, mode: Mode) -> Result<(), RefreshInputError<I2CT>> {
+        let mut expander = self.acquire().await?;
+        expander
+            .set_mode(self.bank(), self.id(), mode)
+            .await
+            .map_err(|e| expander.bus_error(e))
+    }
+}
+
+impl<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC: PinLocation>
+    Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    ACCESS: AccessMode,
+    MODE: PinMode,
+{
+    /// Returns the bank of the pin
+    pub fn bank(&self) -> Bank {
+        self.location.bank()
+    }
+
+    /// Returns the ID of the pin within its bank
+    pub fn id(&self) -> PinID {
+        self.location.id()
+    }
+
+    /// Returns the given pin, if not already taken
+    pub(crate) fn claim(
+        expander: &'a LOCK,
+        wait: &'a WAIT,
+        taken: &'a TakenMask<LOCK::RawMutex>,
+        location: LOC,
+    ) -> Option<Self> {
+        if !taken.claim(location.bank(), location.id()) {
+            return None;
+        }
+
+        Some(Self {
+            expander,
+            wait,
+            taken,
+            location,
+            mode: PhantomData,
+            access_mode: PhantomData,
+            reset: PhantomData,
+        })
+    }
+
+    /// Returns the expander if not locked, used by the blocking trait impls instead of waiting for the lock
+    pub(crate) fn try_lock(&self) -> Result<LOCK::Guard<'a>, WouldBlock> {
+        self.expander.try_lock().ok_or(WouldBlock {})
+    }
+
+    /// Waits for the expander, failing if the device was marked absent
+    pub(crate) async fn acquire(&self) -> Result<LOCK::Guard<'a>, RefreshInputError<I2CT>> {
+        Self::check_presence(self.expander.lock_timeout().await?)
+    }
+
+    /// Returns the expander if not locked, failing if the device was marked absent
+    pub(crate) fn try_acquire(&self) -> Result<LOCK::Guard<'a>, RefreshInputError<I2CT>> {
+        Self::check_presence(self.try_lock()?)
+    }
+
+    fn check_presence(expander: LOCK::Guard<'a>) -> Result<LOCK::Guard<'a>, RefreshInputError<I2CT>> {
+        match expander.is_present() {
+            true => Ok(expander),
+            false => Err(RefreshInputError::DeviceAbsent),
+        }
+    }
+
+    /// Converts into a pin, which direction is switched at runtime
+    /// The current direction is kept.
+    pub fn into_flex_pin(self) -> Pin<'a, I2CT, RESET, LOCK, Flex, ACCESS, WAIT, LOC> {
+        self.convert()
+    }
+
+    /// Converts the pin type, the pin stays taken
+    pub(crate) fn convert<NEWMODE: PinMode, NEWACCESS: AccessMode>(
+        self,
+    ) -> Pin<'a, I2CT, RESET, LOCK, NEWMODE, NEWACCESS, WAIT, LOC> {
+        let pin = Pin {
+            expander: self.expander,
+            wait: self.wait,
+            taken: self.taken,
+            location: self.location,
+            mode: PhantomData,
+            access_mode: PhantomData,
+            reset: PhantomData,
+        };
+
+        // Ownership is handed over to the converted pin
+        core::mem::forget(self);
+        pin
+    }
+}
+
+impl<'a, I2CT, RESET, LOCK, ACCESS, WAIT, LOC: PinLocation> Pin<'a, I2CT, RESET, LOCK, Flex, ACCESS, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    ACCESS: AccessMode,
+{
+    /// Configures the pin as input
+    pub async fn set_as_input(&mut self) -> Result<(), RefreshInputError<I2CT>> {
+        self.change_mode(Mode::Input).await
+    }
+
+    /// Configures the pin as output, driving the current output state
+    pub async fn set_as_output(&mut self) -> Result<(), RefreshInputError<I2CT>> {
+        self.change_mode(Mode::Output).await
+    }
+}
+
+/// Frees the pin, so it may be fetched again
+impl<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC: PinLocation> Drop
+    for Pin<'a, I2CT, RESET, LOCK, MODE, ACCESS, WAIT, LOC>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    MODE: PinMode,
+    ACCESS: AccessMode,
+{
+    fn drop(&mut self) {
+        self.taken.free(self.bank(), self.id());
     }
 }
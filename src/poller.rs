@@ -0,0 +1,199 @@
+//! # Background input poller
+//!
+//! For boards without routed INT line, the [Poller] periodically refreshes the input state into the
+//! cached registers used by [refresh mode pins](crate::pins::RefreshMode) and publishes changes to
+//! its subscribers. As long as the input state does not change, the polling interval is doubled
+//! up to the configured maximum.
+//! ```
+//!# use embassy_futures::select::select;
+//!# use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//!# use embassy_sync::mutex::Mutex;
+//!# use pca9539::example::{DummyDelay, DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::poller::Poller;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//!# let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!# let expander = Mutex::<NoopRawMutex, _>::new(expander);
+//! // Poller supporting up to two subscribers, backing off up to 100 ms
+//! let poller = Poller::<NoopRawMutex, 2>::new().with_max_period(100_000);
+//! let mut changes = poller.receiver().unwrap();
+//!
+//! let subscriber = async {
+//!     let state = changes.changed().await;
+//!     assert_eq!(0b0000_0010, state.bank0);
+//! };
+//!
+//! // Polling every 10 ms, usually spawned as separate task
+//! let polling = poller.run(&expander, DummyDelay::default(), 10_000);
+//!
+//! select(subscriber, polling).await;
+//!# });
+//! ```
+
+use crate::expander::{Bank, RefreshInputError};
+use crate::lock::ExpanderLock;
+use core::cmp::min;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::watch::{Receiver, Watch};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+/// Default maximum interval as multiple of the polling period
+const DEFAULT_BACKOFF_FACTOR: u32 = 8;
+
+/// Input register state of both banks
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct InputState {
+    pub bank0: u8,
+    pub bank1: u8,
+}
+
+/// Publishes changes of the input state to up to `SUBSCRIBERS` receivers
+pub struct InputChanges<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> {
+    state: Watch<RAWMUTEX, InputState, SUBSCRIBERS>,
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> InputChanges<RAWMUTEX, SUBSCRIBERS> {
+    pub const fn new() -> Self {
+        Self { state: Watch::new() }
+    }
+
+    /// Returns a new receiver of input changes. None if the maximum number of subscribers is reached.
+    pub fn receiver(&self) -> Option<Receiver<'_, RAWMUTEX, InputState, SUBSCRIBERS>> {
+        self.state.receiver()
+    }
+
+    /// Returns the last published input state
+    pub fn state(&self) -> Option<InputState> {
+        self.state.try_get()
+    }
+
+    /// Refreshes the input state of the given bank, or both banks if None, and publishes it, if changed
+    /// Returns true if the input state has changed
+    pub async fn refresh<I2CT, RESET, LOCK>(
+        &self,
+        expander: &LOCK,
+        bank: Option<Bank>,
+    ) -> Result<bool, RefreshInputError<I2CT>>
+    where
+        I2CT: I2c,
+        RESET: OutputPin,
+        LOCK: ExpanderLock<I2CT, RESET>,
+    {
+        let state = {
+            let mut expander = expander.lock_timeout().await?;
+
+            match bank {
+                Some(bank) => expander.refresh_input_state(bank).await?,
+                None => {
+                    expander.refresh_input_state(Bank::Bank0).await?;
+                    expander.refresh_input_state(Bank::Bank1).await?;
+                }
+            }
+
+            InputState {
+                bank0: expander.input_state(Bank::Bank0),
+                bank1: expander.input_state(Bank::Bank1),
+            }
+        };
+
+        if self.state.try_get() == Some(state) {
+            return Ok(false);
+        }
+
+        self.state.sender().send(state);
+        Ok(true)
+    }
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> Default for InputChanges<RAWMUTEX, SUBSCRIBERS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Periodically refreshes the input state and publishes changes to up to `SUBSCRIBERS` receivers
+pub struct Poller<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> {
+    changes: InputChanges<RAWMUTEX, SUBSCRIBERS>,
+
+    /// Polled bank, both banks are polled if None
+    bank: Option<Bank>,
+
+    /// Maximum polling interval in microseconds, defaults to a multiple of the polling period
+    max_period_us: Option<u32>,
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> Poller<RAWMUTEX, SUBSCRIBERS> {
+    pub const fn new() -> Self {
+        Self {
+            changes: InputChanges::new(),
+            bank: None,
+            max_period_us: None,
+        }
+    }
+
+    /// Just polls the given bank instead of both banks
+    pub const fn with_bank(mut self, bank: Bank) -> Self {
+        self.bank = Some(bank);
+        self
+    }
+
+    /// Sets the maximum interval in microseconds, the polling interval is backing off to
+    pub const fn with_max_period(mut self, max_period_us: u32) -> Self {
+        self.max_period_us = Some(max_period_us);
+        self
+    }
+
+    /// Returns a new receiver of input changes. None if the maximum number of subscribers is reached.
+    pub fn receiver(&self) -> Option<Receiver<'_, RAWMUTEX, InputState, SUBSCRIBERS>> {
+        self.changes.receiver()
+    }
+
+    /// Returns the last published input state
+    pub fn state(&self) -> Option<InputState> {
+        self.changes.state()
+    }
+
+    /// Polls the input state every `period_us` microseconds. The interval is doubled as long as the
+    /// input state does not change. Failed refreshes are retried after the current interval.
+    pub async fn run<I2CT, RESET, LOCK, DELAY>(&self, expander: &LOCK, mut delay: DELAY, period_us: u32) -> !
+    where
+        I2CT: I2c,
+        RESET: OutputPin,
+        LOCK: ExpanderLock<I2CT, RESET>,
+        DELAY: DelayNs,
+    {
+        let max_period_us = self.max_period_us.unwrap_or(period_us.saturating_mul(DEFAULT_BACKOFF_FACTOR));
+        let mut interval_us = period_us;
+
+        loop {
+            match self.poll(expander).await {
+                Ok(true) => interval_us = period_us,
+                Ok(false) => interval_us = min(interval_us.saturating_mul(2), max_period_us),
+                Err(_) => {}
+            }
+
+            delay.delay_us(interval_us).await;
+        }
+    }
+
+    /// Refreshes the input state once and publishes it, if changed
+    /// Returns true if the input state has changed
+    pub async fn poll<I2CT, RESET, LOCK>(&self, expander: &LOCK) -> Result<bool, RefreshInputError<I2CT>>
+    where
+        I2CT: I2c,
+        RESET: OutputPin,
+        LOCK: ExpanderLock<I2CT, RESET>,
+    {
+        self.changes.refresh(expander, self.bank).await
+    }
+}
+
+impl<RAWMUTEX: RawMutex, const SUBSCRIBERS: usize> Default for Poller<RAWMUTEX, SUBSCRIBERS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,166 @@
+//! # Retry and recovery
+//!
+//! Transient bus errors, like NACKs or arbitration losses on long wires, are retried by the expander itself,
+//! if a [RetryPolicy] is configured. The policy applies to all register reads and writes, so pins don't need
+//! to be wrapped in custom retry loops.
+//!
+//! By default each transfer is attempted just once. Delays between attempts are based on any [DelayNs]
+//! implementation, e.g. `embassy_time::Delay`, so other tasks are running in the meantime.
+//! ```
+//!# use pca9539::example::{DummyDelay, DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::retry::RetryPolicy;
+//!# use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! // Up to three attempts, NACKs are retried after 100 us
+//! let mut expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await.with_retry_policy(
+//!     RetryPolicy::new(3)
+//!         .with_delay(DummyDelay::default(), 100)
+//!         .retry_on(&[ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)]),
+//! );
+//!
+//! expander.refresh_input_state(Bank0).await.unwrap();
+//!# });
+//! ```
+//!
+//! ## Recovery
+//! A [RecoveryPolicy] resets the device after repeated failures. Once the configured number of consecutive
+//! operations failed (after retries), the RESET pin is pulsed, the optional bus recovery callback is invoked and
+//! all cached registers are written again. If the recovery fails itself, it's repeated before the next operation.
+//! ```
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::retry::RecoveryPolicy;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! let mut expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!
+//! // Recover after five consecutive failures, e.g. by clocking SCL nine times
+//! expander.set_recovery_policy(RecoveryPolicy::new(5).with_bus_recovery(|_bus| {}));
+//!# });
+//! ```
+
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use embedded_hal_async::delay::DelayNs;
+
+/// Error kinds retried by default
+pub const TRANSIENT_ERRORS: &[ErrorKind] = &[
+    ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+    ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+    ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+    ErrorKind::ArbitrationLoss,
+];
+
+/// Delay of policies retrying immediately
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoRetryDelay {}
+
+impl DelayNs for NoRetryDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Defines how often and which failed bus transfers are repeated
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy<DELAY = NoRetryDelay> {
+    /// Maximum number of attempts, including the first one
+    max_attempts: u8,
+
+    /// Timer used for the delay between attempts
+    delay: DELAY,
+
+    /// Delay between two attempts in microseconds
+    delay_us: u32,
+
+    /// Error kinds, which are retried
+    retryable: &'static [ErrorKind],
+}
+
+impl RetryPolicy {
+    /// Creates a new policy with the given maximum number of attempts, retrying [TRANSIENT_ERRORS] immediately
+    pub const fn new(max_attempts: u8) -> Self {
+        Self {
+            max_attempts,
+            delay: NoRetryDelay {},
+            delay_us: 0,
+            retryable: TRANSIENT_ERRORS,
+        }
+    }
+}
+
+impl<DELAY: DelayNs> RetryPolicy<DELAY> {
+    /// Waits the given time in microseconds between attempts, based on the given timer
+    pub fn with_delay<D: DelayNs>(self, delay: D, delay_us: u32) -> RetryPolicy<D> {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            delay,
+            delay_us,
+            retryable: self.retryable,
+        }
+    }
+
+    /// Defines the retryable error kinds
+    /// Kinds are compared including their source, e.g. `NoAcknowledge(Address)` does not match `NoAcknowledge(Unknown)`.
+    pub const fn retry_on(mut self, kinds: &'static [ErrorKind]) -> Self {
+        self.retryable = kinds;
+        self
+    }
+
+    /// Returns true if another attempt is allowed after the given failed attempt (starting at 1)
+    pub(crate) fn should_retry(&self, attempt: u8, kind: ErrorKind) -> bool {
+        attempt < self.max_attempts && self.retryable.contains(&kind)
+    }
+
+    /// Waits the configured delay between two attempts
+    pub(crate) async fn wait(&mut self) {
+        if self.delay_us > 0 {
+            self.delay.delay_us(self.delay_us).await;
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Each transfer is attempted just once
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// Defines when and how the device is recovered after repeated failures
+pub struct RecoveryPolicy<B> {
+    /// Number of consecutive failed operations, which trigger the recovery
+    threshold: u8,
+
+    /// Optional callback for recovering the bus, e.g. by clocking SCL until SDA is released
+    bus_recovery: Option<fn(&mut B)>,
+}
+
+impl<B> RecoveryPolicy<B> {
+    /// Creates a new policy, which recovers after the given number of consecutive failures
+    pub const fn new(threshold: u8) -> Self {
+        Self {
+            threshold,
+            bus_recovery: None,
+        }
+    }
+
+    /// Calls the given callback after pulsing the RESET pin
+    pub const fn with_bus_recovery(mut self, callback: fn(&mut B)) -> Self {
+        self.bus_recovery = Some(callback);
+        self
+    }
+
+    /// Returns true if the given number of consecutive failures requires a recovery
+    /// A threshold of zero behaves like one, recovering after each failure.
+    pub(crate) fn is_due(&self, failures: u8) -> bool {
+        failures >= self.threshold.max(1)
+    }
+
+    /// Returns the bus recovery callback
+    pub(crate) fn bus_recovery(&self) -> Option<fn(&mut B)> {
+        self.bus_recovery
+    }
+}
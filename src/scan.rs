@@ -0,0 +1,122 @@
+//! # Address scan
+//!
+//! [scan] probes a range of addresses on a bus and reports which ones answer like a PCA9539, e.g. for backplanes
+//! with a variable number of I/O cards. Each address is verified by [PCA9539::probe], so the scan does not drive
+//! any output.
+//! ```
+//!# use pca9539::example::{DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::scan::{scan, PCA9539_ADDRESSES};
+//!#
+//!# embassy_futures::block_on(async {
+//! let mut i2c_bus = DummyI2CBus::default();
+//! let result = scan(&mut i2c_bus, PCA9539_ADDRESSES).await;
+//!
+//! for address in result.addresses() {
+//!     let _expander = PCA9539::new(DummyI2CBus::default(), address, DummyResetPin::default()).await;
+//! }
+//!
+//! assert_eq!(4, result.count());
+//!# });
+//! ```
+
+use crate::expander::{ProbeError, PCA9539};
+use core::convert::Infallible;
+use core::ops::RangeInclusive;
+use embedded_hal::digital::{ErrorType, OutputPin};
+use embedded_hal_async::i2c::I2c;
+use heapless::Vec;
+
+/// Address range of PCA9539 and TCA9539
+pub const PCA9539_ADDRESSES: RangeInclusive<u8> = 0x74..=0x77;
+
+/// Address range of the variants PCA9535, PCA9555 and TCA9535, sharing the register map of PCA9539
+pub const PCA9535_ADDRESSES: RangeInclusive<u8> = 0x20..=0x27;
+
+/// Maximum number of scanned addresses
+pub const MAX_ADDRESSES: usize = 8;
+
+/// Scan result of an individual address
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DeviceStatus<E> {
+    /// No device acknowledged the address
+    Absent,
+    /// A device answered, but did not behave like a PCA9539
+    Unexpected,
+    /// Device behaves like a PCA9539
+    Present,
+    /// Scan failed due to any other bus error
+    Error(E),
+}
+
+/// Scanned address
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ScanEntry<E> {
+    pub address: u8,
+    pub status: DeviceStatus<E>,
+}
+
+/// Result of [scan], ordered by address
+#[derive(Clone, Debug)]
+pub struct ScanResult<E> {
+    entries: Vec<ScanEntry<E>, MAX_ADDRESSES>,
+}
+
+impl<E> ScanResult<E> {
+    /// Returns all scanned addresses
+    pub fn entries(&self) -> &[ScanEntry<E>] {
+        &self.entries
+    }
+
+    /// Returns the addresses of all devices behaving like a PCA9539
+    pub fn addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.status, DeviceStatus::Present))
+            .map(|entry| entry.address)
+    }
+
+    /// Returns the number of devices behaving like a PCA9539
+    pub fn count(&self) -> usize {
+        self.addresses().count()
+    }
+}
+
+/// Probes each address of the given range
+/// Ranges are limited to [MAX_ADDRESSES], further addresses are ignored.
+pub async fn scan<B: I2c>(bus: &mut B, addresses: RangeInclusive<u8>) -> ScanResult<B::Error> {
+    let mut entries = Vec::new();
+
+    for address in addresses.take(MAX_ADDRESSES) {
+        let mut expander = PCA9539::new(&mut *bus, address, NoResetPin {}).await;
+
+        let status = match expander.probe().await {
+            Ok(()) => DeviceStatus::Present,
+            Err(ProbeError::NoDevice(_)) => DeviceStatus::Absent,
+            Err(ProbeError::UnexpectedDevice) => DeviceStatus::Unexpected,
+            Err(ProbeError::Bus(error)) => DeviceStatus::Error(error),
+        };
+
+        // Capacity matches the number of scanned addresses
+        let _ = entries.push(ScanEntry { address, status });
+    }
+
+    ScanResult { entries }
+}
+
+/// Reset pin of the temporary expanders used for probing
+struct NoResetPin {}
+
+impl ErrorType for NoResetPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoResetPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -1,15 +1,39 @@
+use crate::array::ExpanderArray;
+use crate::debounce::{DebouncePolicy, DebouncedInput, Edge};
+use crate::diagnostics::{LastError, Register};
+use crate::digital_hal_async::{AsyncAdapter, InputPinAsync, OutputPinAsync};
+use crate::dispatcher::{DispatchMode, InterruptDispatcher};
 use crate::expander::Bank::{Bank0, Bank1};
 use crate::expander::Mode::{Input, Output};
 use crate::expander::PinID::{Pin0, Pin1, Pin2, Pin3, Pin4, Pin5, Pin6, Pin7};
-use crate::expander::PCA9539;
-#[cfg(not(feature = "spin"))]
-use crate::guard::LockFreeGuard;
-#[cfg(feature = "spin")]
-use crate::guard::SpinGuard;
-use crate::mocks::{BusMockBuilder, MockI2CBus, WriteError};
+use crate::expander::{ProbeError, RefreshInputError, PCA9539};
+use crate::hotplug::{HotPlug, Presence};
+#[cfg(feature = "critical-section")]
+use crate::lock::CsMutex;
+use crate::lock::{ExpanderLock, WouldBlock};
+use crate::mocks::{
+    BusError, BusMockBuilder, DummyInterruptPin, DummyPin, HangingBus, MockI2CBus, NoDelay, RecordingDelay,
+    RecordingPin, ScanBus, SequencePin,
+};
 use crate::pin_refreshable::{RefreshableInputPin, RefreshableOutputPin};
 use crate::pins::Pins;
-use embedded_hal::digital::v2::{InputPin, IoPin, OutputPin, PinState, StatefulOutputPin, ToggleableOutputPin};
+use crate::poller::{InputChanges, Poller};
+use crate::retry::{RecoveryPolicy, RetryPolicy};
+use crate::scan::{scan, DeviceStatus, ScanEntry, PCA9535_ADDRESSES, PCA9539_ADDRESSES};
+use crate::timeout::{Timeout, TimeoutBus, TimeoutError, TimeoutLock};
+use crate::wait::{InterruptWait, PollingWait};
+use core::cell::RefCell;
+use embassy_futures::join::join;
+use embassy_futures::select::select;
+use embassy_futures::{block_on, yield_now};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use embedded_hal_async::digital::Wait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type ExpanderMutex = Mutex<NoopRawMutex, PCA9539<MockI2CBus, DummyPin>>;
 
 #[test]
 fn test_expander_output_mode_bank0() {
@@ -18,9 +42,9 @@ fn test_expander_output_mode_bank0() {
         .expect_write(1, &[0x06, 0b1111_0110])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode(Bank0, Pin3, Output).unwrap();
-    expander.set_mode(Bank0, Pin0, Output).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode(Bank0, Pin3, Output)).unwrap();
+    block_on(expander.set_mode(Bank0, Pin0, Output)).unwrap();
 }
 
 #[test]
@@ -30,9 +54,9 @@ fn test_expander_output_mode_bank1() {
         .expect_write(1, &[0x07, 0b0011_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode(Bank1, Pin6, Output).unwrap();
-    expander.set_mode(Bank1, Pin7, Output).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode(Bank1, Pin6, Output)).unwrap();
+    block_on(expander.set_mode(Bank1, Pin7, Output)).unwrap();
 }
 
 #[test]
@@ -43,10 +67,10 @@ fn test_expander_input_mode_bank0() {
         .expect_write(1, &[0x06, 0b1000_0100])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank0, Output).unwrap();
-    expander.set_mode(Bank0, Pin2, Input).unwrap();
-    expander.set_mode(Bank0, Pin7, Input).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank0, Output)).unwrap();
+    block_on(expander.set_mode(Bank0, Pin2, Input)).unwrap();
+    block_on(expander.set_mode(Bank0, Pin7, Input)).unwrap();
 }
 
 #[test]
@@ -57,10 +81,10 @@ fn test_expander_input_mode_bank1() {
         .expect_write(1, &[0x07, 0b0000_1001])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank1, Output).unwrap();
-    expander.set_mode(Bank1, Pin0, Input).unwrap();
-    expander.set_mode(Bank1, Pin3, Input).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank1, Output)).unwrap();
+    block_on(expander.set_mode(Bank1, Pin0, Input)).unwrap();
+    block_on(expander.set_mode(Bank1, Pin3, Input)).unwrap();
 }
 
 #[test]
@@ -70,11 +94,11 @@ fn test_expander_state_low_bank0() {
         .expect_write(1, &[0x02, 0b1110_1101])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let mut expander = create_expander(i2c_bus);
     expander.set_state(Bank0, Pin1, false);
-    expander.write_output_state(Bank0).unwrap();
+    block_on(expander.write_output_state(Bank0)).unwrap();
     expander.set_state(Bank0, Pin4, false);
-    expander.write_output_state(Bank0).unwrap();
+    block_on(expander.write_output_state(Bank0)).unwrap();
 }
 
 #[test]
@@ -84,11 +108,11 @@ fn test_expander_state_low_bank1() {
         .expect_write(1, &[0x03, 0b1111_1001])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let mut expander = create_expander(i2c_bus);
     expander.set_state(Bank1, Pin2, false);
-    expander.write_output_state(Bank1).unwrap();
+    block_on(expander.write_output_state(Bank1)).unwrap();
     expander.set_state(Bank1, Pin1, false);
-    expander.write_output_state(Bank1).unwrap();
+    block_on(expander.write_output_state(Bank1)).unwrap();
 }
 
 #[test]
@@ -99,12 +123,12 @@ fn test_expander_state_high_bank0() {
         .expect_write(1, &[0x02, 0b0010_0001])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, false).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, false)).unwrap();
     expander.set_state(Bank0, Pin5, true);
-    expander.write_output_state(Bank0).unwrap();
+    block_on(expander.write_output_state(Bank0)).unwrap();
     expander.set_state(Bank0, Pin0, true);
-    expander.write_output_state(Bank0).unwrap();
+    block_on(expander.write_output_state(Bank0)).unwrap();
 }
 
 #[test]
@@ -115,12 +139,12 @@ fn test_expander_state_high_bank1() {
         .expect_write(1, &[0x03, 0b0101_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank1, false).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank1, false)).unwrap();
     expander.set_state(Bank1, Pin6, true);
-    expander.write_output_state(Bank1).unwrap();
+    block_on(expander.write_output_state(Bank1)).unwrap();
     expander.set_state(Bank1, Pin4, true);
-    expander.write_output_state(Bank1).unwrap();
+    block_on(expander.write_output_state(Bank1)).unwrap();
 }
 
 #[test]
@@ -130,17 +154,17 @@ fn test_set_mode_all_input_bank0() {
         .expect_write(1, &[0x06, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank0, Output).unwrap();
-    expander.set_mode_all(Bank0, Input).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank0, Output)).unwrap();
+    block_on(expander.set_mode_all(Bank0, Input)).unwrap();
 }
 
 #[test]
 fn test_set_mode_all_output_bank0() {
     let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x06, 0b0000_0000]).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank0, Output).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank0, Output)).unwrap();
 }
 
 #[test]
@@ -150,17 +174,17 @@ fn test_set_mode_all_input_bank1() {
         .expect_write(1, &[0x07, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank1, Output).unwrap();
-    expander.set_mode_all(Bank1, Input).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank1, Output)).unwrap();
+    block_on(expander.set_mode_all(Bank1, Input)).unwrap();
 }
 
 #[test]
 fn test_set_mode_all_output_bank1() {
     let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x07, 0b0000_0000]).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_mode_all(Bank1, Output).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode_all(Bank1, Output)).unwrap();
 }
 
 #[test]
@@ -170,9 +194,9 @@ fn test_set_state_all_low_bank0() {
         .expect_write(1, &[0x02, 0b0000_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, true).unwrap();
-    expander.set_state_all(Bank0, false).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, true)).unwrap();
+    block_on(expander.set_state_all(Bank0, false)).unwrap();
 }
 
 #[test]
@@ -182,25 +206,25 @@ fn test_set_state_all_low_bank1() {
         .expect_write(1, &[0x03, 0b0000_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank1, true).unwrap();
-    expander.set_state_all(Bank1, false).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank1, true)).unwrap();
+    block_on(expander.set_state_all(Bank1, false)).unwrap();
 }
 
 #[test]
 fn test_set_state_all_high_bank0() {
     let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x02, 0b1111_1111]).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, true).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, true)).unwrap();
 }
 
 #[test]
 fn test_set_state_all_high_bank1() {
     let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x03, 0b1111_1111]).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank1, true).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank1, true)).unwrap();
 }
 
 #[test]
@@ -211,63 +235,149 @@ fn test_reverse_polarity_bank0() {
         .expect_write(1, &[0x04, 0b0001_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.reverse_polarity(Bank0, Pin2, true).unwrap();
-    expander.reverse_polarity(Bank0, Pin4, true).unwrap();
-    expander.reverse_polarity(Bank0, Pin2, false).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.reverse_polarity(Bank0, Pin2, true)).unwrap();
+    block_on(expander.reverse_polarity(Bank0, Pin4, true)).unwrap();
+    block_on(expander.reverse_polarity(Bank0, Pin2, false)).unwrap();
 }
 
 #[test]
-fn test_refresh_input_state_bank0_success() {
+fn test_unchanged_registers_not_written() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x00])
-        .expect_read(1, 0b0001_0000)
+        .expect_write(1, &[0x06, 0b1111_0111])
+        .expect_write(1, &[0x03, 0b0000_0000])
+        .expect_write(1, &[0x04, 0b0000_0100])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.refresh_input_state(Bank0).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode(Bank0, Pin3, Output)).unwrap();
+    block_on(expander.set_mode(Bank0, Pin3, Output)).unwrap();
+
+    block_on(expander.set_state_all(Bank1, false)).unwrap();
+    block_on(expander.set_state_all(Bank1, false)).unwrap();
+
+    block_on(expander.reverse_polarity(Bank0, Pin2, true)).unwrap();
+    block_on(expander.reverse_polarity(Bank0, Pin2, true)).unwrap();
 }
 
 #[test]
-fn test_refresh_input_state_bank1_success() {
+fn test_first_write_not_skipped() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x01])
-        .expect_read(1, 0b0001_0000)
+        .expect_write(1, &[0x02, 0b1111_1111])
+        .expect_write(1, &[0x07, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.refresh_input_state(Bank1).unwrap();
+    // Cached state equals the power-on defaults, but the device state is unknown
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, true)).unwrap();
+    block_on(expander.set_mode_all(Bank1, Input)).unwrap();
 }
 
 #[test]
-fn test_refresh_input_state_write_error() {
-    let i2c_bus = BusMockBuilder::new().write_error(0x00).into_mock();
+fn test_flush_writes_dirty_registers() {
+    let i2c_bus = BusMockBuilder::new()
+        .mock_write(6) // Initial state
+        .expect_write(1, &[0x03, 0b1111_1101])
+        .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let result = expander.refresh_input_state(Bank0);
+    let mut expander = create_expander(i2c_bus);
+    assert!(expander.is_dirty());
+    block_on(expander.flush()).unwrap();
+    assert!(!expander.is_dirty());
 
-    assert_eq!("WriteError", result.unwrap_err().to_string().as_str());
+    expander.set_state(Bank1, Pin1, false);
+    expander.set_state(Bank0, Pin1, true);
+    assert!(expander.is_dirty());
+
+    block_on(expander.flush()).unwrap();
+    assert!(!expander.is_dirty());
+
+    block_on(expander.flush()).unwrap();
 }
 
 #[test]
-fn test_refresh_input_state_read_error() {
-    let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x00]).read_error().into_mock();
+fn test_batch_writes_outputs_before_configuration() {
+    let i2c_bus = BusMockBuilder::new()
+        .mock_write(6) // Initial state
+        .expect_write(1, &[0x02, 0b1111_1001])
+        .expect_write(1, &[0x06, 0b1111_1001])
+        .expect_write(1, &[0x05, 0b0010_0000])
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.flush()).unwrap();
+
+    block_on(expander.batch(|b| {
+        b.set_mode(Bank0, Pin1, Output);
+        b.set(Bank0, Pin1, false);
+        b.set_mode(Bank0, Pin2, Output);
+        b.set(Bank0, Pin2, false);
+        b.set_polarity(Bank1, Pin5, true);
+        b.set(Bank1, Pin3, true);
+    }))
+    .unwrap();
+}
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let result = expander.refresh_input_state(Bank0);
+#[test]
+fn test_batch_write_error() {
+    let i2c_bus = BusMockBuilder::new().write_error(0x02).into_mock();
 
-    assert_eq!("ReadError", result.unwrap_err().to_string());
+    let mut expander = create_expander(i2c_bus);
+    let result = block_on(expander.batch(|b| b.set(Bank0, Pin0, false)));
+    assert_eq!(BusError::Error1, result.unwrap_err());
 }
 
 #[test]
-fn test_is_pin_high_bank0() {
+fn test_flush_write_error_keeps_dirty() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x00])
-        .expect_read(1, 0b0111_1010)
+        .mock_write(6)
+        .write_error(0x02)
+        .expect_write(1, &[0x02, 0b1111_1110])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.refresh_input_state(Bank0).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.flush()).unwrap();
+
+    expander.set_state(Bank0, Pin0, false);
+    assert_eq!(BusError::Error1, block_on(expander.flush()).unwrap_err());
+    assert!(expander.is_dirty());
+
+    block_on(expander.flush()).unwrap();
+    assert!(!expander.is_dirty());
+}
+
+#[test]
+fn test_refresh_input_state_bank0_success() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x00, 0b0001_0000).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+}
+
+#[test]
+fn test_refresh_input_state_bank1_success() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x01, 0b0001_0000).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.refresh_input_state(Bank1)).unwrap();
+}
+
+#[test]
+fn test_refresh_input_state_read_error() {
+    let i2c_bus = BusMockBuilder::new().read_error(0x00).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    let result = block_on(expander.refresh_input_state(Bank0));
+
+    assert_eq!("I2cError", result.unwrap_err().to_string());
+}
+
+#[test]
+fn test_is_pin_high_bank0() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x00, 0b0111_1010).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
 
     assert!(!expander.is_pin_input_high(Bank0, Pin7));
     assert!(expander.is_pin_input_high(Bank0, Pin6));
@@ -282,13 +392,10 @@ fn test_is_pin_high_bank0() {
 
 #[test]
 fn test_is_pin_high_bank1() {
-    let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x01])
-        .expect_read(1, 0b0100_0111)
-        .into_mock();
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x01, 0b0100_0111).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.refresh_input_state(Bank1).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.refresh_input_state(Bank1)).unwrap();
 
     assert!(!expander.is_pin_input_high(Bank1, Pin7));
     assert!(expander.is_pin_input_high(Bank1, Pin6));
@@ -304,14 +411,14 @@ fn test_is_pin_high_bank1() {
 #[test]
 fn test_regular_pin_input_bank0() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(4, &[0x00])
-        .expect_read(2, 0b0000_0100)
-        .expect_read(2, 0b0100_0000)
+        .expect_read(2, 0x00, 0b0000_0100)
+        .expect_read(2, 0x00, 0b0100_0000)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank0, Pin2);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank0, Pin2).unwrap();
 
     assert!(pin.is_high().unwrap());
     assert!(!pin.is_low().unwrap());
@@ -322,14 +429,14 @@ fn test_regular_pin_input_bank0() {
 #[test]
 fn test_regular_pin_input_bank1() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(4, &[0x01])
-        .expect_read(2, 0b0100_0100)
-        .expect_read(2, 0b0000_0000)
+        .expect_read(2, 0x01, 0b0100_0100)
+        .expect_read(2, 0x01, 0b0000_0000)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank1, Pin6);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank1, Pin6).unwrap();
 
     assert!(pin.is_high().unwrap());
     assert!(!pin.is_low().unwrap());
@@ -337,49 +444,39 @@ fn test_regular_pin_input_bank1() {
     assert!(pin.is_low().unwrap());
 }
 
-#[test]
-fn test_regular_pin_input_write_error() {
-    let i2c_bus = BusMockBuilder::new().write_error(0x01).into_mock();
-
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank1, Pin6);
-
-    assert_eq!("WriteError", pin.is_high().unwrap_err().to_string())
-}
-
 #[test]
 fn test_regular_pin_input_read_error() {
-    let i2c_bus = BusMockBuilder::new().mock_write(1).read_error().into_mock();
+    let i2c_bus = BusMockBuilder::new().read_error(0x01).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank1, Pin6);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank1, Pin6).unwrap();
 
-    assert_eq!("ReadError", pin.is_high().unwrap_err().to_string())
+    assert_eq!("I2cError", pin.is_high().unwrap_err().to_string())
 }
 
 #[test]
 fn test_refreshable_pin_input_bank0() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(2, &[0x00])
-        .expect_read(1, 0b0000_0100)
-        .expect_read(1, 0b0100_1000)
+        .expect_read(1, 0x00, 0b0000_0100)
+        .expect_read(1, 0x00, 0b0100_1000)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
 
-    let pin02 = pins.get_refreshable_pin(Bank0, Pin2);
-    let pin03 = pins.get_refreshable_pin(Bank0, Pin3);
+    let mut pin02 = pins.get_refreshable_pin(Bank0, Pin2).unwrap();
+    let mut pin03 = pins.get_refreshable_pin(Bank0, Pin3).unwrap();
 
-    pin02.refresh_bank().unwrap();
+    block_on(pin02.refresh_bank()).unwrap();
     assert!(pin02.is_high().unwrap());
     assert!(!pin02.is_low().unwrap());
     assert!(!pin03.is_high().unwrap());
     assert!(pin03.is_low().unwrap());
 
-    pin03.refresh_bank().unwrap();
+    block_on(pin03.refresh_bank()).unwrap();
     assert!(!pin02.is_high().unwrap());
     assert!(pin02.is_low().unwrap());
     assert!(pin03.is_high().unwrap());
@@ -389,24 +486,24 @@ fn test_refreshable_pin_input_bank0() {
 #[test]
 fn test_refreshable_pin_input_bank1() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(2, &[0x01])
-        .expect_read(1, 0b0010_0100)
-        .expect_read(1, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0010_0100)
+        .expect_read(1, 0x01, 0b0000_0000)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
 
-    let pin12 = pins.get_refreshable_pin(Bank1, Pin2);
-    let pin15 = pins.get_refreshable_pin(Bank1, Pin5);
+    let mut pin12 = pins.get_refreshable_pin(Bank1, Pin2).unwrap();
+    let mut pin15 = pins.get_refreshable_pin(Bank1, Pin5).unwrap();
 
-    pin12.refresh_bank().unwrap();
+    block_on(pin12.refresh_bank()).unwrap();
     assert!(pin12.is_high().unwrap());
     assert!(!pin12.is_low().unwrap());
     assert!(pin15.is_high().unwrap());
     assert!(!pin15.is_low().unwrap());
 
-    pin15.refresh_bank().unwrap();
+    block_on(pin15.refresh_bank()).unwrap();
     assert!(!pin12.is_high().unwrap());
     assert!(pin12.is_low().unwrap());
     assert!(!pin15.is_high().unwrap());
@@ -416,118 +513,83 @@ fn test_refreshable_pin_input_bank1() {
 #[test]
 fn test_refreshable_pin_input_mixed_banks() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x00])
-        .expect_write(1, &[0x01])
-        .expect_write(1, &[0x00])
-        .expect_write(1, &[0x01])
-        .expect_read(1, 0b0001_0001)
-        .expect_read(1, 0b1000_0000)
-        .expect_read(1, 0b0000_0001)
-        .expect_read(1, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0001_0001)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
 
-    let pin00 = pins.get_refreshable_pin(Bank0, Pin0);
-    let pin17 = pins.get_refreshable_pin(Bank1, Pin7);
+    let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).unwrap();
+    let mut pin17 = pins.get_refreshable_pin(Bank1, Pin7).unwrap();
 
-    pin00.refresh_all().unwrap();
+    block_on(pin00.refresh_all()).unwrap();
     assert!(pin00.is_high().unwrap());
     assert!(!pin00.is_low().unwrap());
     assert!(pin17.is_high().unwrap());
     assert!(!pin17.is_low().unwrap());
 
-    pin17.refresh_all().unwrap();
+    block_on(pin17.refresh_all()).unwrap();
     assert!(pin00.is_high().unwrap());
     assert!(!pin00.is_low().unwrap());
     assert!(!pin17.is_high().unwrap());
     assert!(pin17.is_low().unwrap());
 }
 
-#[test]
-fn test_refreshable_pin_refresh_bank_write_error() {
-    let i2c_bus = BusMockBuilder::new().write_error(0x0).into_mock();
-
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-
-    let pin = pins.get_refreshable_pin(Bank0, Pin0);
-    let error = pin.refresh_bank().unwrap_err();
-
-    assert_eq!("WriteError", error.to_string());
-    assert!(pin.is_low().unwrap());
-}
-
 #[test]
 fn test_refreshable_pin_refresh_bank_read_error() {
-    let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x00]).read_error().into_mock();
-
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-
-    let pin = pins.get_refreshable_pin(Bank0, Pin0);
-    let error = pin.refresh_bank().unwrap_err();
-
-    assert_eq!("ReadError", error.to_string());
-    assert!(pin.is_low().unwrap());
-}
-
-#[test]
-fn test_refreshable_pin_refresh_all_write_error() {
-    let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x0])
-        .expect_read(1, 0b0001_0000)
-        .write_error(0x1)
-        .into_mock();
+    let i2c_bus = BusMockBuilder::new().read_error(0x00).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
 
-    let pin = pins.get_refreshable_pin(Bank0, Pin0);
-    let error = pin.refresh_all().unwrap_err();
+    let mut pin = pins.get_refreshable_pin(Bank0, Pin0).unwrap();
+    let error = block_on(pin.refresh_bank()).unwrap_err();
 
-    assert_eq!("WriteError", error.to_string());
+    assert_eq!("I2cError", error.to_string());
     assert!(pin.is_low().unwrap());
 }
 
 #[test]
 fn test_refreshable_pin_refresh_all_read_error() {
     let i2c_bus = BusMockBuilder::new()
-        .expect_write(1, &[0x0])
-        .expect_read(1, 0b0001_0000)
-        .expect_write(1, &[0x1])
-        .read_error()
+        .expect_read(1, 0x00, 0b0001_0000)
+        .read_error(0x01)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
 
-    let pin = pins.get_refreshable_pin(Bank0, Pin0);
-    let error = pin.refresh_all().unwrap_err();
+    let mut pin = pins.get_refreshable_pin(Bank0, Pin0).unwrap();
+    let error = block_on(pin.refresh_all()).unwrap_err();
 
-    assert_eq!("ReadError", error.to_string());
+    assert_eq!("I2cError", error.to_string());
     assert!(pin.is_low().unwrap());
 }
 
 #[test]
 fn test_regular_pin_set_output_state() {
     let i2c_bus = BusMockBuilder::new()
-        .mock_write(6) // Mode switch
+        .mock_write(5) // Mode switch, second output write of Bank 0 is skipped
         .expect_write(1, &[0x03, 0b1111_1011])
         .expect_write(1, &[0x02, 0b1110_1111])
         .expect_write(1, &[0x02, 0b1110_1110])
         .expect_write(1, &[0x02, 0b1111_1110])
-        .expect_write(1, &[0x02, 0b1111_1110])
         .expect_write(1, &[0x02, 0b1111_1111])
         .expect_write(1, &[0x03, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin00 = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::High).unwrap();
-    let mut pin04 = pins.get_pin(Bank0, Pin4).into_output_pin(PinState::High).unwrap();
-    let mut pin12 = pins.get_pin(Bank1, Pin2).into_output_pin(PinState::High).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin00 = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
+    let mut pin04 = block_on(pins.get_pin(Bank0, Pin4).unwrap().into_output_pin(PinState::High)).unwrap();
+    let mut pin12 = block_on(pins.get_pin(Bank1, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
 
     pin12.set_low().unwrap();
     assert!(pin12.is_set_low().unwrap());
@@ -562,63 +624,73 @@ fn test_regular_pin_set_output_state() {
 fn test_regular_pin_set_low_write_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(2).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
 
     let result = pin.set_low();
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
 fn test_regular_pin_set_high_write_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(2).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::Low)).unwrap();
 
     let result = pin.set_high();
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
 fn test_regular_pin_set_state_write_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(2).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::Low)).unwrap();
 
     let result = pin.set_state(PinState::High);
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
 fn test_refreshable_pin_set_output_state() {
     let i2c_bus = BusMockBuilder::new()
         .mock_write(2) // setting all low
-        .mock_write(16) // mode switch
+        .mock_write(8) // mode switch, output registers are already low
         .expect_write(1, &[0x02, 0b0000_0110]) // Update Bank 0
         .expect_write(1, &[0x03, 0b1110_0000]) // Update Bank 1
-        .expect_write(1, &[0x02, 0b0000_0110]) // Update all
-        .expect_write(1, &[0x03, 0b1110_0000]) // Update all
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, false).unwrap();
-    expander.set_state_all(Bank1, false).unwrap();
-
-    let pins = get_pins(&mut expander);
-    let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
-    let mut pin01 = pins.get_refreshable_pin(Bank0, Pin1).into_output_pin(PinState::Low).unwrap();
-    let mut pin02 = pins.get_refreshable_pin(Bank0, Pin2).into_output_pin(PinState::Low).unwrap();
-    let mut pin03 = pins.get_refreshable_pin(Bank0, Pin3).into_output_pin(PinState::Low).unwrap();
-
-    let mut pin14 = pins.get_refreshable_pin(Bank1, Pin4).into_output_pin(PinState::Low).unwrap();
-    let mut pin15 = pins.get_refreshable_pin(Bank1, Pin5).into_output_pin(PinState::Low).unwrap();
-    let mut pin16 = pins.get_refreshable_pin(Bank1, Pin6).into_output_pin(PinState::Low).unwrap();
-    let mut pin17 = pins.get_refreshable_pin(Bank1, Pin7).into_output_pin(PinState::Low).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, false)).unwrap();
+    block_on(expander.set_state_all(Bank1, false)).unwrap();
+
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin00 =
+        block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin01 =
+        block_on(pins.get_refreshable_pin(Bank0, Pin1).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin02 =
+        block_on(pins.get_refreshable_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin03 =
+        block_on(pins.get_refreshable_pin(Bank0, Pin3).unwrap().into_output_pin(PinState::Low)).unwrap();
+
+    let mut pin14 =
+        block_on(pins.get_refreshable_pin(Bank1, Pin4).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin15 =
+        block_on(pins.get_refreshable_pin(Bank1, Pin5).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin16 =
+        block_on(pins.get_refreshable_pin(Bank1, Pin6).unwrap().into_output_pin(PinState::Low)).unwrap();
+    let mut pin17 =
+        block_on(pins.get_refreshable_pin(Bank1, Pin7).unwrap().into_output_pin(PinState::Low)).unwrap();
 
     pin00.set_low().unwrap();
     assert!(pin00.is_set_low().unwrap());
@@ -652,21 +724,25 @@ fn test_refreshable_pin_set_output_state() {
     assert!(!pin17.is_set_low().unwrap());
     assert!(pin17.is_set_high().unwrap());
 
-    pin03.update_bank().unwrap();
-    pin16.update_bank().unwrap();
-    pin17.update_all().unwrap();
+    block_on(pin03.update_bank()).unwrap();
+    block_on(pin16.update_bank()).unwrap();
+
+    // Both banks are unchanged, so no writes are issued
+    block_on(pin17.update_all()).unwrap();
 }
 
 #[test]
 fn test_refreshable_pin_update_bank_write_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(2).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::Low).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::Low)).unwrap();
+    pin.set_high().unwrap();
 
-    let result = pin.update_bank();
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    let result = block_on(pin.update_bank());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
@@ -677,12 +753,14 @@ fn test_refreshable_pin_update_all_write_error() {
         .write_error(0x3)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_refreshable_pin(Bank1, Pin0).into_output_pin(PinState::Low).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_refreshable_pin(Bank1, Pin0).unwrap().into_output_pin(PinState::Low)).unwrap();
+    pin.set_high().unwrap();
 
-    let result = pin.update_all();
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    let result = block_on(pin.update_all());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
@@ -693,10 +771,11 @@ fn test_regular_pin_into_output_pin() {
         .expect_write(1, &[0x02, 0b0000_0001])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, false).unwrap();
-    let pins = get_pins(&mut expander);
-    let _pin = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::High).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, false)).unwrap();
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let _pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
 }
 
 #[test]
@@ -706,24 +785,21 @@ fn test_regular_pin_into_input_pin() {
         .expect_write(1, &[0x06, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-
-    let pins = get_pins(&mut expander);
-    let _pin = pins
-        .get_pin(Bank0, Pin0)
-        .into_output_pin(PinState::High)
-        .unwrap()
-        .into_input_pin()
-        .unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let output_pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
+    let _pin = block_on(output_pin.into_input_pin()).unwrap();
 }
 
 #[test]
 fn test_regular_pin_into_output_pin_mode_switch_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x6).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -732,9 +808,10 @@ fn test_regular_pin_into_output_pin_mode_switch_error() {
 fn test_regular_pin_into_output_pin_state_set_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(1).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -743,9 +820,10 @@ fn test_regular_pin_into_output_pin_state_set_error() {
 fn test_regular_pin_into_input_pin_mode_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x6).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -758,10 +836,11 @@ fn test_refreshable_pin_into_output_pin() {
         .expect_write(1, &[0x02, 0b0000_0001])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    expander.set_state_all(Bank0, false).unwrap();
-    let pins = get_pins(&mut expander);
-    let _pin = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::High).unwrap();
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_state_all(Bank0, false)).unwrap();
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let _pin = block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
 }
 
 #[test]
@@ -771,24 +850,22 @@ fn test_refreshable_pin_into_input_pin() {
         .expect_write(1, &[0x06, 0b1111_1111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-
-    let pins = get_pins(&mut expander);
-    let _pin = pins
-        .get_refreshable_pin(Bank0, Pin0)
-        .into_output_pin(PinState::High)
-        .unwrap()
-        .into_input_pin()
-        .unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let output_pin =
+        block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
+    let _pin = block_on(output_pin.into_input_pin()).unwrap();
 }
 
 #[test]
 fn test_refreshable_pin_into_output_pin_mode_switch_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x6).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -797,9 +874,10 @@ fn test_refreshable_pin_into_output_pin_mode_switch_error() {
 fn test_refreshable_pin_into_output_pin_state_set_error() {
     let i2c_bus = BusMockBuilder::new().mock_write(1).write_error(0x2).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -808,9 +886,10 @@ fn test_refreshable_pin_into_output_pin_state_set_error() {
 fn test_refreshable_pin_into_input_pin_mode_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x6).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let result = pins.get_refreshable_pin(Bank0, Pin0).into_output_pin(PinState::High);
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_refreshable_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High));
 
     assert!(result.is_err())
 }
@@ -822,9 +901,10 @@ fn test_regular_pin_toggle() {
         .expect_write(1, &[0x02, 0b1111_1011])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_pin(Bank0, Pin2).into_output_pin(PinState::High).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
 
     pin.toggle().unwrap();
 }
@@ -836,12 +916,13 @@ fn test_regular_pin_toggle_error() {
         .write_error(0x2)
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_pin(Bank0, Pin2).into_output_pin(PinState::High).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
 
     let result = pin.toggle();
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
@@ -851,12 +932,14 @@ fn test_refreshable_pin_toggle() {
         .expect_write(1, &[0x02, 0b1111_0111])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_refreshable_pin(Bank0, Pin3).into_output_pin(PinState::High).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin =
+        block_on(pins.get_refreshable_pin(Bank0, Pin3).unwrap().into_output_pin(PinState::High)).unwrap();
 
     pin.toggle().unwrap();
-    pin.update_bank().unwrap();
+    block_on(pin.update_bank()).unwrap();
 }
 
 #[test]
@@ -865,9 +948,11 @@ fn test_refreshable_pin_toggle_no_update() {
         .mock_write(2) // Mode switch
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
-    let pins = get_pins(&mut expander);
-    let mut pin = pins.get_refreshable_pin(Bank0, Pin3).into_output_pin(PinState::High).unwrap();
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin =
+        block_on(pins.get_refreshable_pin(Bank0, Pin3).unwrap().into_output_pin(PinState::High)).unwrap();
 
     pin.toggle().unwrap();
 }
@@ -879,26 +964,28 @@ fn test_regular_pin_invert_polarity() {
         .expect_write(1, &[0x04, 0b0000_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let expander = create_expander(i2c_bus);
 
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank0, Pin4);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let pin = pins.get_pin(Bank0, Pin4).unwrap();
 
-    pin.invert_polarity(true).unwrap();
-    pin.invert_polarity(false).unwrap();
+    block_on(pin.invert_polarity(true)).unwrap();
+    block_on(pin.invert_polarity(false)).unwrap();
 }
 
 #[test]
 fn test_regular_pin_invert_polarity_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x04).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let expander = create_expander(i2c_bus);
 
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_pin(Bank0, Pin4);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let pin = pins.get_pin(Bank0, Pin4).unwrap();
 
-    let result = pin.invert_polarity(true);
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    let result = block_on(pin.invert_polarity(true));
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
 #[test]
@@ -908,36 +995,1614 @@ fn test_refreshable_pin_invert_polarity() {
         .expect_write(1, &[0x05, 0b0000_0000])
         .into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let expander = create_expander(i2c_bus);
 
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_refreshable_pin(Bank1, Pin5);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let pin = pins.get_refreshable_pin(Bank1, Pin5).unwrap();
 
-    pin.invert_polarity(true).unwrap();
-    pin.invert_polarity(false).unwrap();
+    block_on(pin.invert_polarity(true)).unwrap();
+    block_on(pin.invert_polarity(false)).unwrap();
 }
 
 #[test]
 fn test_refreshable_pin_invert_polarity_error() {
     let i2c_bus = BusMockBuilder::new().write_error(0x05).into_mock();
 
-    let mut expander = PCA9539::new(i2c_bus, 0x74);
+    let expander = create_expander(i2c_bus);
 
-    let pins = get_pins(&mut expander);
-    let pin = pins.get_refreshable_pin(Bank1, Pin4);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let pin = pins.get_refreshable_pin(Bank1, Pin4).unwrap();
 
-    let result = pin.invert_polarity(true);
-    assert_eq!(WriteError::Error1, result.unwrap_err());
+    let result = block_on(pin.invert_polarity(true));
+    assert_eq!("I2cError", result.unwrap_err().to_string());
 }
 
-/// Testing spin based RefGuard
-#[cfg(feature = "spin")]
-fn get_pins(expander: &mut PCA9539<MockI2CBus>) -> Pins<MockI2CBus, SpinGuard<MockI2CBus>> {
-    expander.pins_spin_mutex()
+#[test]
+fn test_regular_pin_wait_for_high() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0100)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_pin(Bank0, Pin2).unwrap();
+
+    block_on(pin.wait_for_high()).unwrap();
+}
+
+#[test]
+fn test_regular_pin_wait_for_rising_edge() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = InterruptWait::<NoopRawMutex, _>::new(DummyInterruptPin {});
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_pin(Bank1, Pin7).unwrap();
+
+    block_on(pin.wait_for_rising_edge()).unwrap();
+}
+
+#[test]
+fn test_regular_pin_wait_for_any_edge() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x01, 0b0000_0010)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0010)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_pin(Bank1, Pin1).unwrap();
+
+    block_on(pin.wait_for_any_edge()).unwrap();
+}
+
+#[test]
+fn test_regular_pin_wait_for_low_error() {
+    let i2c_bus = BusMockBuilder::new().read_error(0x00).into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_pin(Bank0, Pin0).unwrap();
+
+    let result = block_on(pin.wait_for_low());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
+}
+
+#[test]
+fn test_refreshable_pin_wait_for_falling_edge() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin00 = pins.get_refreshable_pin(Bank0, Pin0).unwrap();
+    let mut pin01 = pins.get_refreshable_pin(Bank0, Pin1).unwrap();
+
+    block_on(pin00.wait_for_falling_edge()).unwrap();
+    assert!(pin00.is_low().unwrap());
+    assert!(pin01.is_low().unwrap());
+}
+
+#[test]
+fn test_refreshable_pin_wait_for_high_ignores_errors() {
+    let i2c_bus = BusMockBuilder::new()
+        .read_error(0x00)
+        .expect_read(1, 0x00, 0b0000_1000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_refreshable_pin(Bank0, Pin3).unwrap();
+
+    block_on(pin.wait_for_high()).unwrap();
+    assert!(pin.is_high().unwrap());
+}
+
+#[test]
+fn test_poller_poll_publishes_changes() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .expect_read(1, 0x00, 0b0000_0011)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let poller = Poller::<NoopRawMutex, 1>::new();
+    let mut receiver = poller.receiver().unwrap();
+
+    assert!(block_on(poller.poll(&expander)).unwrap());
+    let state = receiver.try_changed().unwrap();
+    assert_eq!(0b0000_0001, state.bank0);
+    assert_eq!(0b1000_0000, state.bank1);
+
+    assert!(!block_on(poller.poll(&expander)).unwrap());
+    assert!(receiver.try_changed().is_none());
+
+    assert!(block_on(poller.poll(&expander)).unwrap());
+    assert_eq!(0b0000_0011, receiver.try_changed().unwrap().bank0);
+}
+
+#[test]
+fn test_poller_poll_single_bank() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x01, 0b0100_0000).into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let poller = Poller::<NoopRawMutex, 1>::new().with_bank(Bank1);
+
+    assert!(block_on(poller.poll(&expander)).unwrap());
+    assert_eq!(0b0100_0000, poller.state().unwrap().bank1);
+
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_refreshable_pin(Bank1, Pin6).unwrap();
+    assert!(pin.is_high().unwrap());
+}
+
+#[test]
+fn test_poller_poll_error() {
+    let i2c_bus = BusMockBuilder::new().read_error(0x00).into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let poller = Poller::<NoopRawMutex, 1>::new();
+
+    let result = block_on(poller.poll(&expander));
+    assert_eq!("I2cError", result.unwrap_err().to_string());
+    assert!(poller.state().is_none());
+}
+
+#[test]
+fn test_poller_run_backoff() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(4, 0x00, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .read_error(0x00)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let poller = Poller::<NoopRawMutex, 1>::new().with_bank(Bank0).with_max_period(350);
+
+    let intervals = RefCell::new(Vec::new());
+    let delay = RecordingDelay { intervals: &intervals };
+    block_on(select(poller.run(&expander, delay, 100), async {
+        while intervals.borrow().len() < 8 {
+            yield_now().await;
+        }
+    }));
+
+    assert_eq!(vec![100, 200, 350, 350, 100, 200, 200, 350], *intervals.borrow());
+}
+
+#[test]
+fn test_debounced_input_samples() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let pin = pins.get_pin(Bank0, Pin0).unwrap();
+    let mut debounced = DebouncedInput::new(pin, DebouncePolicy::Samples(3));
+
+    assert_eq!(None, debounced.update(0).unwrap());
+    assert!(debounced.is_low().unwrap());
+
+    // Bouncing
+    assert_eq!(None, debounced.update(0).unwrap());
+    assert_eq!(None, debounced.update(0).unwrap());
+    assert!(debounced.is_low().unwrap());
+
+    assert_eq!(None, debounced.update(0).unwrap());
+    assert_eq!(None, debounced.update(0).unwrap());
+    assert_eq!(Some(Edge::Rising), debounced.update(0).unwrap());
+    assert!(debounced.is_high().unwrap());
+}
+
+#[test]
+fn test_debounced_input_window() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x01, 0b0001_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0001_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    let bank1 = pins.get_refreshable_pin(Bank1, Pin0).unwrap();
+    let mut debounced = DebouncedInput::new(
+        pins.get_refreshable_pin(Bank1, Pin4).unwrap(),
+        DebouncePolicy::Window(5_000),
+    );
+
+    let mut edges = Vec::new();
+    for now_us in [0, 1_000, 2_000, 3_000, 7_000, 8_000] {
+        block_on(bank1.refresh_bank()).unwrap();
+        edges.push(debounced.update(now_us).unwrap());
+    }
+
+    assert_eq!(vec![None, None, None, None, None, Some(Edge::Falling)], edges);
+    assert!(debounced.is_low().unwrap());
+}
+
+#[test]
+fn test_dispatcher_dispatch_all() {
+    let bus_1 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+    let bus_2 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .into_mock();
+
+    let expander_1 = ExpanderMutex::new(create_expander(bus_1));
+    let expander_2 = ExpanderMutex::new(create_expander(bus_2));
+    let changes_1 = InputChanges::<NoopRawMutex, 1>::new();
+    let changes_2 = InputChanges::<NoopRawMutex, 1>::new();
+
+    let mut dispatcher = InterruptDispatcher::<_, _, _, _, 2, 1>::new(DispatchMode::All);
+    dispatcher.register(&expander_1, &changes_1).unwrap();
+    dispatcher.register(&expander_2, &changes_2).unwrap();
+    assert!(dispatcher.register(&expander_2, &changes_2).is_err());
+
+    let mut int = SequencePin { levels: vec![] };
+    block_on(dispatcher.dispatch_released(&mut int)).unwrap();
+    assert_eq!(0b0000_0001, changes_1.state().unwrap().bank0);
+    assert_eq!(0b1000_0000, changes_2.state().unwrap().bank1);
+}
+
+#[test]
+fn test_dispatcher_dispatch_until_released() {
+    let bus_1 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+    let bus_2 = BusMockBuilder::new().into_mock();
+
+    let expander_1 = RefCell::new(create_expander(bus_1));
+    let expander_2 = RefCell::new(create_expander(bus_2));
+    let changes_1 = InputChanges::<NoopRawMutex, 1>::new();
+    let changes_2 = InputChanges::<NoopRawMutex, 1>::new();
+
+    let mut dispatcher = InterruptDispatcher::<_, _, _, _, 4, 1>::new(DispatchMode::UntilReleased);
+    dispatcher.register(&expander_1, &changes_1).unwrap();
+    dispatcher.register(&expander_2, &changes_2).unwrap();
+
+    let mut int = SequencePin { levels: vec![true] };
+    block_on(dispatcher.dispatch_released(&mut int)).unwrap();
+    assert_eq!(0b0000_0001, changes_1.state().unwrap().bank0);
+    assert!(changes_2.state().is_none());
+}
+
+#[test]
+fn test_dispatcher_dispatch_error() {
+    let bus_1 = BusMockBuilder::new().read_error(0x00).into_mock();
+    let bus_2 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0100)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+
+    let expander_1 = ExpanderMutex::new(create_expander(bus_1));
+    let expander_2 = ExpanderMutex::new(create_expander(bus_2));
+    let changes_1 = InputChanges::<NoopRawMutex, 1>::new();
+    let changes_2 = InputChanges::<NoopRawMutex, 1>::new();
+
+    let mut dispatcher = InterruptDispatcher::<_, _, _, _, 4, 1>::new(DispatchMode::All);
+    dispatcher.register(&expander_1, &changes_1).unwrap();
+    dispatcher.register(&expander_2, &changes_2).unwrap();
+
+    let result = block_on(dispatcher.dispatch());
+    assert_eq!("I2cError", result.unwrap_err().to_string());
+    assert!(changes_1.state().is_none());
+    assert_eq!(0b0000_0100, changes_2.state().unwrap().bank0);
+}
+
+#[test]
+fn test_array_locate() {
+    let array = ExpanderArray::new([
+        ExpanderMutex::new(create_expander(BusMockBuilder::new().into_mock())),
+        ExpanderMutex::new(create_expander(BusMockBuilder::new().into_mock())),
+    ]);
+
+    assert_eq!(32, array.pin_count());
+    assert!(matches!(array.locate(0), Some((0, Bank0, Pin0))));
+    assert!(matches!(array.locate(11), Some((0, Bank1, Pin3))));
+    assert!(matches!(array.locate(23), Some((1, Bank0, Pin7))));
+    assert!(matches!(array.locate(31), Some((1, Bank1, Pin7))));
+    assert!(array.locate(32).is_none());
+    assert!(array.get_pin(32).is_none());
+    assert!(array.get_refreshable_pin(32).is_none());
+}
+
+#[test]
+fn test_array_get_pin() {
+    let bus_1 = BusMockBuilder::new().into_mock();
+    let bus_2 = BusMockBuilder::new().expect_read(1, 0x01, 0b0000_0100).into_mock();
+
+    let array = ExpanderArray::new([
+        RefCell::new(create_expander(bus_1)),
+        RefCell::new(create_expander(bus_2)),
+    ]);
+
+    let mut pin = array.get_pin(26).unwrap();
+    assert!(pin.is_high().unwrap());
+}
+
+#[test]
+fn test_array_refresh_all() {
+    let bus_1 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+    let bus_2 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b1000_0000)
+        .into_mock();
+
+    let array = ExpanderArray::new([
+        ExpanderMutex::new(create_expander(bus_1)),
+        ExpanderMutex::new(create_expander(bus_2)),
+    ]);
+    let mut pin_0 = array.get_refreshable_pin(0).unwrap();
+    let mut pin_31 = array.get_refreshable_pin(31).unwrap();
+    let mut pin_30 = array.get_refreshable_pin(30).unwrap();
+
+    block_on(array.refresh_all()).unwrap();
+    assert!(pin_0.is_high().unwrap());
+    assert!(pin_31.is_high().unwrap());
+    assert!(pin_30.is_low().unwrap());
+}
+
+#[test]
+fn test_array_refresh_all_error() {
+    let bus_1 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x01, 0b0000_0000)
+        .into_mock();
+    let bus_2 = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .read_error(0x01)
+        .into_mock();
+
+    let array = ExpanderArray::new([
+        ExpanderMutex::new(create_expander(bus_1)),
+        ExpanderMutex::new(create_expander(bus_2)),
+    ]);
+
+    let error = block_on(array.refresh_all()).unwrap_err();
+    assert_eq!(1, error.device);
+    assert_eq!("I2cError", error.error.to_string());
+}
+
+#[test]
+fn test_array_flush_all() {
+    let bus_1 = BusMockBuilder::new()
+        .expect_write(1, &[0x02, 0b1111_0111])
+        .expect_write(1, &[0x03, 0b1111_1111])
+        .expect_write(1, &[0x04, 0b0000_0000])
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_write(1, &[0x05, 0b0000_0000])
+        .expect_write(1, &[0x07, 0b1111_1111])
+        .into_mock();
+    let bus_2 = BusMockBuilder::new().write_error(0x02).into_mock();
+
+    let array = ExpanderArray::new([
+        ExpanderMutex::new(create_expander(bus_1)),
+        ExpanderMutex::new(create_expander(bus_2)),
+    ]);
+    block_on(array.device(0).unwrap().lock()).set_state(Bank0, Pin3, false);
+
+    let error = block_on(array.flush_all()).unwrap_err();
+    assert_eq!(1, error.device);
+    assert_eq!("I2cError", error.error.to_string());
+}
+
+#[test]
+fn test_expander_release() {
+    let i2c_bus = BusMockBuilder::new().expect_write(1, &[0x06, 0b1111_1110]).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode(Bank0, Pin0, Output)).unwrap();
+
+    let (mut i2c_bus, _reset_pin) = expander.release();
+    i2c_bus.checkpoint();
+}
+
+#[test]
+fn test_pins_release() {
+    let i2c_bus = BusMockBuilder::new().into_mock();
+
+    let expander = create_expander(i2c_bus);
+    let expander = ExpanderMutex::new(expander);
+    let pins = Pins::new(&expander);
+    {
+        let _pin = pins.get_pin(Bank0, Pin0).unwrap();
+    }
+
+    let mut guard = block_on(pins.release()).ok().unwrap();
+    guard.bus_mut().checkpoint();
+    assert!(expander.try_lock().is_err());
+    drop(guard);
+
+    let (mut i2c_bus, _reset_pin) = expander.into_inner().release();
+    i2c_bus.checkpoint();
+}
+
+#[test]
+fn test_pins_release_leaked_pin() {
+    let i2c_bus = BusMockBuilder::new().into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    core::mem::forget(pins.get_pin(Bank1, Pin2).unwrap());
+
+    let pins = block_on(pins.release()).err().unwrap();
+    assert!(pins.is_taken(Bank1, Pin2));
+}
+
+#[test]
+fn test_pins_ownership_second_claim() {
+    let i2c_bus = BusMockBuilder::new().into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+
+    let _pin = pins.get_pin(Bank1, Pin3).unwrap();
+    assert!(pins.is_taken(Bank1, Pin3));
+    assert!(!pins.is_taken(Bank0, Pin3));
+    assert!(pins.get_pin(Bank1, Pin3).is_none());
+    assert!(pins.get_refreshable_pin(Bank1, Pin3).is_none());
+    assert!(pins.get_pin(Bank0, Pin3).is_some());
+}
+
+#[test]
+fn test_pins_ownership_freed_on_drop() {
+    let i2c_bus = BusMockBuilder::new().into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+
+    {
+        let _pin = pins.get_refreshable_pin(Bank0, Pin7).unwrap();
+        assert!(pins.is_taken(Bank0, Pin7));
+    }
+
+    assert!(!pins.is_taken(Bank0, Pin7));
+    assert!(pins.get_pin(Bank0, Pin7).is_some());
+}
+
+#[test]
+fn test_pins_ownership_kept_on_conversion() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x06, 0b1111_1011])
+        .expect_write(1, &[0x02, 0b1111_1111])
+        .write_error(0x07)
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+
+    let pin = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
+    assert!(pins.is_taken(Bank0, Pin2));
+    drop(pin);
+    assert!(!pins.is_taken(Bank0, Pin2));
+
+    // Pin is freed, if the conversion fails
+    let result = block_on(pins.get_pin(Bank1, Pin2).unwrap().into_output_pin(PinState::High));
+    assert!(result.is_err());
+    assert!(!pins.is_taken(Bank1, Pin2));
+}
+
+#[test]
+fn test_array_ownership() {
+    let array = ExpanderArray::new([
+        ExpanderMutex::new(create_expander(BusMockBuilder::new().into_mock())),
+        ExpanderMutex::new(create_expander(BusMockBuilder::new().into_mock())),
+    ]);
+
+    let pin = array.get_pin(20).unwrap();
+    assert!(array.get_refreshable_pin(20).is_none());
+    assert!(array.get_pin(4).is_some());
+
+    drop(pin);
+    assert!(array.get_refreshable_pin(20).is_some());
+}
+
+#[test]
+fn test_pins_split() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x01, 0b0010_0000)
+        .expect_write(1, &[0x06, 0b1111_1101])
+        .expect_write(1, &[0x02, 0b1111_1101])
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut parts = pins.split().unwrap();
+
+    assert!(matches!(parts.p1_5.bank(), Bank1));
+    assert!(matches!(parts.p1_5.id(), Pin5));
+    assert!(parts.p1_5.is_high().unwrap());
+
+    let mut pin = block_on(parts.p0_1.into_output_pin(PinState::Low)).unwrap();
+    assert!(matches!(pin.bank(), Bank0));
+    assert!(matches!(pin.id(), Pin1));
+    assert!(pin.is_set_low().unwrap());
+}
+
+#[test]
+fn test_pins_split_ownership() {
+    let i2c_bus = BusMockBuilder::new().into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+
+    {
+        let _pin = pins.get_pin(Bank1, Pin0).unwrap();
+        assert!(pins.split().is_none());
+    }
+
+    let parts = pins.split().unwrap();
+    assert!(pins.split().is_none());
+    assert!(pins.get_pin(Bank0, Pin6).is_none());
+
+    drop(parts.p0_6);
+    assert!(pins.get_pin(Bank0, Pin6).is_some());
+    assert!(pins.is_taken(Bank1, Pin7));
+}
+
+#[test]
+fn test_regular_pin_into_refresh_mode() {
+    let i2c_bus = BusMockBuilder::new()
+        .mock_write(2)
+        .expect_write(1, &[0x02, 0b1111_1110])
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let pin = block_on(pins.get_pin(Bank0, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
+
+    let mut pin = pin.into_refresh_mode();
+    pin.set_low().unwrap();
+    assert!(pin.is_set_low().unwrap());
+    block_on(pin.update_bank()).unwrap();
+}
+
+#[test]
+fn test_refreshable_pin_into_regular_mode() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_read(1, 0x00, 0b0000_1000)
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let pin = pins.get_refreshable_pin(Bank0, Pin3).unwrap();
+    block_on(pin.refresh_bank()).unwrap();
+
+    let mut pin = pin.into_regular_mode();
+    assert!(pin.is_high().unwrap());
+    assert!(pins.is_taken(Bank0, Pin3));
+}
+
+#[test]
+fn test_regular_flex_pin() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x02, 0b1011_1111])
+        .expect_write(1, &[0x06, 0b1011_1111])
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_read(1, 0x00, 0b0100_0000)
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank0, Pin6).unwrap().into_flex_pin();
+
+    pin.set_low().unwrap();
+    block_on(pin.set_as_output()).unwrap();
+    assert!(pin.is_set_low().unwrap());
+    assert!(pin.is_low().unwrap());
+
+    block_on(pin.set_as_input()).unwrap();
+    assert!(pin.is_high().unwrap());
+    assert!(pins.is_taken(Bank0, Pin6));
+}
+
+#[test]
+fn test_refreshable_flex_pin() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x07, 0b1111_1101])
+        .expect_write(1, &[0x03, 0b1111_1101])
+        .expect_write(1, &[0x07, 0b1111_1111])
+        .expect_read(1, 0x01, 0b0000_0010)
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_refreshable_pin(Bank1, Pin1).unwrap().into_flex_pin();
+
+    block_on(pin.set_as_output()).unwrap();
+    pin.set_low().unwrap();
+    assert!(pin.is_set_low().unwrap());
+    block_on(pin.update_bank()).unwrap();
+
+    block_on(pin.set_as_input()).unwrap();
+    block_on(pin.refresh_bank()).unwrap();
+    assert!(pin.is_high().unwrap());
+}
+
+#[test]
+fn test_flex_pin_set_as_output_error() {
+    let i2c_bus = BusMockBuilder::new().write_error(0x06).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank0, Pin0).unwrap().into_flex_pin();
+
+    assert_eq!("I2cError", block_on(pin.set_as_output()).unwrap_err().to_string());
+}
+
+#[test]
+fn test_async_adapter_input() {
+    let mut pin = AsyncAdapter::new(SequencePin {
+        levels: vec![true, false],
+    });
+
+    assert!(block_on(is_high_generic(&mut pin)).unwrap());
+    assert!(block_on(pin.is_low_async()).unwrap());
+    assert!(pin.release().levels.is_empty());
+}
+
+#[test]
+fn test_async_adapter_generic_driver() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x01, 0b0000_0100).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut expander_pin = pins.get_pin(Bank1, Pin2).unwrap();
+    let mut native_pin = AsyncAdapter::new(SequencePin { levels: vec![false] });
+
+    assert!(block_on(is_high_generic(&mut expander_pin)).unwrap());
+    assert!(!block_on(is_high_generic(&mut native_pin)).unwrap());
+
+    let mut output_pin = AsyncAdapter::new(DummyPin {});
+    block_on(output_pin.set_state_async(PinState::High)).unwrap();
+}
+
+async fn is_high_generic<P: InputPinAsync>(pin: &mut P) -> Result<bool, P::Error> {
+    pin.is_high_async().await
+}
+
+#[test]
+fn test_open_drain_pin() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_write(1, &[0x02, 0b1111_1011])
+        .expect_write(1, &[0x06, 0b1111_1011])
+        .expect_read(1, 0x00, 0b0000_0000)
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_read(1, 0x00, 0b0000_0000)
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_open_drain_pin(PinState::High)).unwrap();
+
+    pin.set_low().unwrap();
+    assert!(pin.is_low().unwrap());
+
+    // Line is held low by another device
+    pin.set_high().unwrap();
+    assert!(pin.is_low().unwrap());
+}
+
+#[test]
+fn test_open_drain_pin_from_output_high() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x06, 0b1111_0111])
+        .expect_write(1, &[0x02, 0b1111_1111])
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_write(1, &[0x02, 0b1111_0111])
+        .into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let pin = block_on(pins.get_pin(Bank0, Pin3).unwrap().into_output_pin(PinState::High)).unwrap();
+
+    // Line is released before clearing the latch, so it is never driven low
+    block_on(pin.into_open_drain_pin(PinState::High)).unwrap();
+}
+
+#[test]
+fn test_open_drain_pin_latch_error() {
+    let i2c_bus = BusMockBuilder::new().write_error(0x03).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let result = block_on(pins.get_pin(Bank1, Pin2).unwrap().into_open_drain_pin(PinState::Low));
+
+    assert_eq!("I2cError", result.err().unwrap().to_string());
+    assert!(!pins.is_taken(Bank1, Pin2));
+}
+
+#[test]
+fn test_open_drain_pin_mode_error() {
+    let i2c_bus = BusMockBuilder::new().mock_write(2).write_error(0x06).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin5).unwrap().into_open_drain_pin(PinState::High)).unwrap();
+
+    assert_eq!("I2cError", pin.set_low().unwrap_err().to_string());
+}
+
+#[test]
+fn test_lock_refcell() {
+    let expander = RefCell::new(create_expander(lock_backend_bus()));
+    assert_lock_backend(&expander);
+}
+
+#[test]
+fn test_lock_embassy_mutex() {
+    let expander = ExpanderMutex::new(create_expander(lock_backend_bus()));
+    assert_lock_backend(&expander);
+}
+
+#[cfg(feature = "critical-section")]
+#[test]
+fn test_lock_critical_section() {
+    let expander = CsMutex::new(create_expander(lock_backend_bus()));
+    assert_lock_backend(&expander);
+    expander.into_inner();
+}
+
+#[cfg(feature = "spin")]
+#[test]
+fn test_lock_spin() {
+    let expander = spin::Mutex::new(create_expander(lock_backend_bus()));
+    assert_lock_backend(&expander);
+}
+
+#[test]
+fn test_lock_waits_for_release() {
+    let expander = RefCell::new(create_expander(BusMockBuilder::new().into_mock()));
+    let guard = expander.try_lock().unwrap();
+
+    block_on(join(ExpanderLock::lock(&expander), async {
+        yield_now().await;
+        drop(guard);
+    }));
+}
+
+#[test]
+fn test_regular_pin_would_block() {
+    let i2c_bus = BusMockBuilder::new().mock_write(2).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut input = pins.get_pin(Bank0, Pin1).unwrap();
+    let mut output = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
+
+    let guard = expander.try_lock().unwrap();
+    assert_eq!("WouldBlock", input.is_high().unwrap_err().to_string());
+    assert_eq!("WouldBlock", output.set_low().unwrap_err().to_string());
+    assert_eq!("WouldBlock", output.is_set_high().unwrap_err().to_string());
+    drop(guard);
+
+    assert!(output.is_set_high().unwrap());
+}
+
+#[test]
+fn test_refreshable_pin_would_block() {
+    let i2c_bus = BusMockBuilder::new().mock_write(2).into_mock();
+
+    let expander = ExpanderMutex::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut input = pins.get_refreshable_pin(Bank0, Pin1).unwrap();
+    let mut output =
+        block_on(pins.get_refreshable_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::High)).unwrap();
+
+    let guard = expander.try_lock().unwrap();
+    assert_eq!("WouldBlock", input.is_high().unwrap_err().to_string());
+    assert_eq!("WouldBlock", output.set_low().unwrap_err().to_string());
+    assert_eq!("WouldBlock", output.is_set_high().unwrap_err().to_string());
+    drop(guard);
+
+    output.set_low().unwrap();
+    assert!(output.is_set_low().unwrap());
+}
+
+#[test]
+fn test_timeout_bus_hanging() {
+    let mut expander = block_on(PCA9539::new(
+        TimeoutBus::new(HangingBus {}, NoDelay {}, 10_000),
+        0x74,
+        DummyPin {},
+    ));
+
+    let result = block_on(expander.refresh_input_state(Bank0));
+    assert!(matches!(
+        result,
+        Err(RefreshInputError::I2cError(TimeoutError::Timeout))
+    ));
+}
+
+#[test]
+fn test_timeout_bus_completed() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x02, 0b1111_1110])
+        .write_error(0x03)
+        .into_mock();
+
+    let mut expander = block_on(PCA9539::new(
+        TimeoutBus::new(i2c_bus, NoDelay {}, 10_000),
+        0x74,
+        DummyPin {},
+    ));
+    expander.set_state(Bank0, Pin0, false);
+
+    block_on(expander.write_output_state(Bank0)).unwrap();
+    assert_eq!(
+        TimeoutError::Bus(BusError::Error1),
+        block_on(expander.write_output_state(Bank1)).unwrap_err()
+    );
+}
+
+#[test]
+fn test_timeout_lock() {
+    let expander = TimeoutLock::new(
+        RefCell::new(create_expander(BusMockBuilder::new().into_mock())),
+        NoDelay {},
+        10_000,
+    );
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank0, Pin1).unwrap();
+
+    let guard = expander.try_lock().unwrap();
+    assert_eq!("Timeout", block_on(pin.is_high_async()).unwrap_err().to_string());
+    drop(guard);
+
+    assert!(block_on(expander.lock_timeout()).is_ok());
+}
+
+#[test]
+fn test_timeout_lock_pin_operations() {
+    let expander = TimeoutLock::new(
+        RefCell::new(create_expander(BusMockBuilder::new().into_mock())),
+        NoDelay {},
+        10_000,
+    );
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let input = pins.get_pin(Bank0, Pin1).unwrap();
+    let mut flex = pins.get_pin(Bank0, Pin2).unwrap().into_flex_pin();
+    let mut refreshable = pins.get_refreshable_pin(Bank1, Pin0).unwrap();
+    let output = pins.get_refreshable_pin(Bank1, Pin1).unwrap();
+
+    let guard = expander.try_lock().unwrap();
+    assert_eq!(
+        "Timeout",
+        block_on(input.invert_polarity(true)).unwrap_err().to_string()
+    );
+    assert_eq!("Timeout", block_on(flex.set_as_output()).unwrap_err().to_string());
+    assert_eq!(
+        "Timeout",
+        block_on(refreshable.is_high_async()).unwrap_err().to_string()
+    );
+    assert_eq!(
+        "Timeout",
+        block_on(refreshable.wait_for_any_edge()).unwrap_err().to_string()
+    );
+    assert!(matches!(
+        block_on(output.into_output_pin(PinState::Low)),
+        Err(RefreshInputError::Timeout)
+    ));
+    assert!(block_on(pins.diagnostics_async()).is_err());
+    drop(guard);
+
+    assert!(!pins.is_taken(Bank1, Pin1));
+}
+
+#[test]
+fn test_timeout_lock_helpers() {
+    let array = ExpanderArray::new([TimeoutLock::new(
+        RefCell::new(create_expander(BusMockBuilder::new().into_mock())),
+        NoDelay {},
+        10_000,
+    )]);
+    let hot_plug = HotPlug::<NoopRawMutex, 1>::new();
+
+    let guard = array.device(0).unwrap().try_lock().unwrap();
+    assert_eq!("Timeout", block_on(array.refresh_all()).unwrap_err().error.to_string());
+    assert_eq!("Timeout", block_on(array.flush_all()).unwrap_err().error.to_string());
+    assert_eq!(
+        Timeout {},
+        block_on(hot_plug.check(array.device(0).unwrap())).unwrap_err()
+    );
+    assert!(hot_plug.presence().is_none());
+    drop(guard);
+}
+
+#[test]
+fn test_retry_write_after_nack() {
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x02, BusError::Nack)
+        .write_failure(0x02, BusError::ArbitrationLoss)
+        .expect_write(1, &[0x02, 0b1111_1110])
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_retry_policy(RetryPolicy::new(3));
+    expander.set_state(Bank0, Pin0, false);
+
+    block_on(expander.write_output_state(Bank0)).unwrap();
+}
+
+#[test]
+fn test_retry_read() {
+    let i2c_bus = BusMockBuilder::new()
+        .read_failure(0x01, BusError::Nack)
+        .expect_read(1, 0x01, 0b0000_0100)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_retry_policy(RetryPolicy::new(2));
+
+    block_on(expander.refresh_input_state(Bank1)).unwrap();
+    assert!(expander.is_pin_input_high(Bank1, Pin2));
+}
+
+#[test]
+fn test_retry_attempts_exhausted() {
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x06, BusError::Nack)
+        .write_failure(0x06, BusError::Nack)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_retry_policy(RetryPolicy::new(2));
+
+    assert_eq!(
+        BusError::Nack,
+        block_on(expander.set_mode(Bank0, Pin1, Output)).unwrap_err()
+    );
+}
+
+#[test]
+fn test_retry_non_retryable_error() {
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x04, BusError::Error1)
+        .write_failure(0x05, BusError::ArbitrationLoss)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander
+        .set_retry_policy(RetryPolicy::new(3).retry_on(&[ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)]));
+
+    assert_eq!(
+        BusError::Error1,
+        block_on(expander.reverse_polarity(Bank0, Pin1, true)).unwrap_err()
+    );
+    assert_eq!(
+        BusError::ArbitrationLoss,
+        block_on(expander.reverse_polarity(Bank1, Pin1, true)).unwrap_err()
+    );
+}
+
+#[test]
+fn test_retry_disabled_by_default() {
+    let i2c_bus = BusMockBuilder::new().read_failure(0x00, BusError::Nack).into_mock();
+    let mut expander = create_expander(i2c_bus);
+
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+}
+
+#[test]
+fn test_retry_delay() {
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x03, BusError::Nack)
+        .write_failure(0x03, BusError::Nack)
+        .expect_write(1, &[0x03, 0b0111_1111])
+        .into_mock();
+
+    let intervals = RefCell::new(Vec::new());
+    let delay = RecordingDelay { intervals: &intervals };
+
+    let mut expander = create_expander(i2c_bus).with_retry_policy(RetryPolicy::new(3).with_delay(delay, 10));
+    expander.set_state(Bank1, Pin7, false);
+
+    block_on(expander.write_output_state(Bank1)).unwrap();
+    assert_eq!(vec![10, 10], *intervals.borrow());
+}
+
+#[test]
+fn test_recovery_after_consecutive_failures() {
+    static RECOVERIES: AtomicUsize = AtomicUsize::new(0);
+
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x02, BusError::Nack)
+        .write_failure(0x02, BusError::Nack)
+        .expect_write(1, &[0x02, 0b1111_1110])
+        .expect_write(1, &[0x03, 0b1111_1111])
+        .expect_write(1, &[0x04, 0b0000_0000])
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_write(1, &[0x05, 0b0000_0000])
+        .expect_write(1, &[0x07, 0b1111_1111])
+        .into_mock();
+
+    let levels = RefCell::new(Vec::new());
+    let mut expander = block_on(PCA9539::new(i2c_bus, 0x74, RecordingPin { levels: &levels }));
+    expander.set_recovery_policy(RecoveryPolicy::new(2).with_bus_recovery(|_| {
+        RECOVERIES.fetch_add(1, Ordering::Relaxed);
+    }));
+    expander.set_state(Bank0, Pin0, false);
+
+    assert_eq!(
+        BusError::Nack,
+        block_on(expander.write_output_state(Bank0)).unwrap_err()
+    );
+    assert_eq!(0, RECOVERIES.load(Ordering::Relaxed));
+
+    assert_eq!(
+        BusError::Nack,
+        block_on(expander.write_output_state(Bank0)).unwrap_err()
+    );
+    assert_eq!(1, RECOVERIES.load(Ordering::Relaxed));
+    assert_eq!(vec![true, false, true], *levels.borrow());
+    assert!(!expander.is_dirty());
+}
+
+#[test]
+fn test_recovery_success_resets_failures() {
+    let i2c_bus = BusMockBuilder::new()
+        .read_failure(0x00, BusError::Nack)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .read_failure(0x00, BusError::Nack)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_recovery_policy(RecoveryPolicy::new(2));
+
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+}
+
+#[test]
+fn test_recovery_repeated_before_next_operation() {
+    let i2c_bus = BusMockBuilder::new()
+        .read_failure(0x00, BusError::Nack)
+        .write_failure(0x02, BusError::Nack)
+        .mock_write(6)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_recovery_policy(RecoveryPolicy::new(1));
+
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+    assert!(expander.is_dirty());
+
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+    assert!(expander.is_pin_input_high(Bank0, Pin0));
+    assert!(!expander.is_dirty());
+}
+
+#[test]
+fn test_recover_manually() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x06, 0b1111_1101])
+        .mock_write(6)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.set_mode(Bank0, Pin1, Output)).unwrap();
+
+    block_on(expander.recover()).unwrap();
+    assert!(!expander.is_dirty());
+}
+
+#[test]
+fn test_diagnostics_counters() {
+    let i2c_bus = BusMockBuilder::new()
+        .write_failure(0x02, BusError::Nack)
+        .expect_write(1, &[0x02, 0b1111_1110])
+        .read_error(0x01)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_retry_policy(RetryPolicy::new(2));
+    expander.set_state(Bank0, Pin0, false);
+
+    block_on(expander.write_output_state(Bank0)).unwrap();
+    assert!(block_on(expander.refresh_input_state(Bank1)).is_err());
+
+    let diagnostics = expander.diagnostics();
+    assert_eq!(2, diagnostics.writes);
+    assert_eq!(1, diagnostics.reads);
+    assert_eq!(1, diagnostics.retries);
+    assert_eq!(2, diagnostics.errors());
+    assert_eq!(1, diagnostics.register_errors(Register::Output0));
+    assert_eq!(1, diagnostics.register_errors(Register::Input1));
+    assert_eq!(0, diagnostics.register_errors(Register::Input0));
+    assert_eq!(
+        1,
+        diagnostics.kind_errors(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    );
+    assert_eq!(1, diagnostics.kind_errors(ErrorKind::Other));
+    assert_eq!(0, diagnostics.kind_errors(ErrorKind::Bus));
+    assert_eq!(
+        Some(LastError {
+            register: Register::Input1,
+            kind: ErrorKind::Other,
+            sequence: 3,
+        }),
+        diagnostics.last_error
+    );
+
+    expander.reset_diagnostics();
+    assert_eq!(0, expander.diagnostics().writes);
+    assert!(expander.diagnostics().last_error.is_none());
+}
+
+#[test]
+fn test_diagnostics_through_pins() {
+    let i2c_bus = BusMockBuilder::new().expect_read(1, 0x00, 0b0000_0010).into_mock();
+
+    let expander = RefCell::new(create_expander(i2c_bus));
+    let pins = Pins::new(&expander);
+    let mut pin = pins.get_pin(Bank0, Pin1).unwrap();
+    assert!(pin.is_high().unwrap());
+
+    let guard = expander.try_lock().unwrap();
+    assert_eq!(WouldBlock {}, pins.diagnostics().unwrap_err());
+    drop(guard);
+
+    assert_eq!(1, pins.diagnostics().unwrap().reads);
+    assert_eq!(0, block_on(pins.diagnostics_async()).unwrap().writes);
+}
+
+#[test]
+fn test_probe_success() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x04, 0b0000_0001)
+        .expect_read(1, 0x05, 0b0000_0000)
+        .expect_write(1, &[0x04, 0b0101_0101])
+        .expect_write(1, &[0x05, 0b1010_1010])
+        .expect_read(1, 0x04, 0b0101_0101)
+        .expect_read(1, 0x05, 0b1010_1010)
+        .expect_write(1, &[0x04, 0b1010_1010])
+        .expect_write(1, &[0x05, 0b0101_0101])
+        .expect_read(1, 0x04, 0b1010_1010)
+        .expect_read(1, 0x05, 0b0101_0101)
+        .expect_write(1, &[0x04, 0b0000_0001])
+        .expect_write(1, &[0x05, 0b0000_0000])
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.probe()).unwrap();
+}
+
+#[test]
+fn test_probe_no_device() {
+    let i2c_bus = BusMockBuilder::new().read_failure(0x04, BusError::Nack).into_mock();
+    let mut expander = create_expander(i2c_bus);
+
+    assert_eq!(
+        ProbeError::NoDevice(BusError::Nack),
+        block_on(expander.probe()).unwrap_err()
+    );
+}
+
+#[test]
+fn test_probe_no_recovery() {
+    let i2c_bus = BusMockBuilder::new().read_failure(0x04, BusError::Nack).into_mock();
+
+    let levels = RefCell::new(Vec::new());
+    let mut expander = block_on(PCA9539::new(i2c_bus, 0x74, RecordingPin { levels: &levels }));
+    expander.set_recovery_policy(RecoveryPolicy::new(1));
+    expander.enable_hot_plug(1);
+
+    assert_eq!(
+        ProbeError::NoDevice(BusError::Nack),
+        block_on(expander.probe()).unwrap_err()
+    );
+    assert_eq!(vec![true], *levels.borrow());
+    assert!(expander.is_present());
+}
+
+#[test]
+fn test_probe_bus_error() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x04, 0b0000_0000)
+        .expect_read(1, 0x05, 0b0000_0000)
+        .write_error(0x04)
+        .expect_write(1, &[0x04, 0b0000_0000])
+        .expect_write(1, &[0x05, 0b0000_0000])
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    assert_eq!(
+        ProbeError::Bus(BusError::Error1),
+        block_on(expander.probe()).unwrap_err()
+    );
+}
+
+#[test]
+fn test_probe_unexpected_device() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x04, 0b1111_1111)
+        .expect_read(1, 0x05, 0b1111_1111)
+        .mock_write(2)
+        .expect_read(1, 0x04, 0b1111_1111)
+        .expect_read(1, 0x05, 0b1111_1111)
+        .expect_write(1, &[0x04, 0b1111_1111])
+        .expect_write(1, &[0x05, 0b1111_1111])
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    assert_eq!(ProbeError::UnexpectedDevice, block_on(expander.probe()).unwrap_err());
+}
+
+#[test]
+fn test_probe_data_nack() {
+    let i2c_bus = BusMockBuilder::new().read_failure(0x04, BusError::DataNack).into_mock();
+    let mut expander = create_expander(i2c_bus);
+
+    assert_eq!(ProbeError::UnexpectedDevice, block_on(expander.probe()).unwrap_err());
+}
+
+#[test]
+fn test_scan() {
+    let mut i2c_bus = ScanBus {
+        expanders: vec![0x74, 0x76],
+        foreign: vec![0x77],
+        ..Default::default()
+    };
+
+    let result = block_on(scan(&mut i2c_bus, PCA9539_ADDRESSES));
+
+    assert_eq!(
+        &[
+            ScanEntry {
+                address: 0x74,
+                status: DeviceStatus::Present
+            },
+            ScanEntry {
+                address: 0x75,
+                status: DeviceStatus::Absent
+            },
+            ScanEntry {
+                address: 0x76,
+                status: DeviceStatus::Present
+            },
+            ScanEntry {
+                address: 0x77,
+                status: DeviceStatus::Unexpected
+            },
+        ],
+        result.entries()
+    );
+    assert_eq!(vec![0x74, 0x76], result.addresses().collect::<Vec<_>>());
+    assert_eq!(2, result.count());
+}
+
+#[test]
+fn test_scan_variant_range() {
+    let mut i2c_bus = ScanBus {
+        expanders: vec![0x27],
+        failing: vec![0x21],
+        ..Default::default()
+    };
+
+    let result = block_on(scan(&mut i2c_bus, PCA9535_ADDRESSES));
+
+    assert_eq!(8, result.entries().len());
+    assert_eq!(
+        DeviceStatus::Error(BusError::ArbitrationLoss),
+        result.entries()[1].status
+    );
+    assert_eq!(vec![0x27], result.addresses().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scan_data_nack() {
+    let mut i2c_bus = ScanBus {
+        expanders: vec![0x74],
+        rejecting: vec![0x75],
+        ..Default::default()
+    };
+
+    let result = block_on(scan(&mut i2c_bus, PCA9539_ADDRESSES));
+
+    assert_eq!(DeviceStatus::Unexpected, result.entries()[1].status);
+    assert_eq!(vec![0x74], result.addresses().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scan_range_limited() {
+    let mut i2c_bus = ScanBus::default();
+    let result = block_on(scan(&mut i2c_bus, 0x08..=0x77));
+
+    assert_eq!(8, result.entries().len());
+    assert_eq!(0x0F, result.entries()[7].address);
+    assert_eq!(0, result.count());
+}
+
+#[test]
+fn test_hot_plug_marks_absent() {
+    let i2c_bus = BusMockBuilder::new()
+        .mock_write(2)
+        .write_failure(0x02, BusError::Nack)
+        .write_failure(0x02, BusError::Nack)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.enable_hot_plug(2);
+
+    let expander = RefCell::new(expander);
+    let pins = Pins::new(&expander);
+    let mut output = block_on(pins.get_pin(Bank0, Pin1).unwrap().into_output_pin(PinState::High)).unwrap();
+    let mut input = pins.get_pin(Bank0, Pin2).unwrap();
+    let mut flex = pins.get_pin(Bank0, Pin3).unwrap().into_flex_pin();
+    let refreshable = pins.get_refreshable_pin(Bank1, Pin0).unwrap().into_output_pin(PinState::High);
+
+    assert_eq!("I2cError", block_on(output.set_low_async()).unwrap_err().to_string());
+    assert_eq!(
+        "DeviceAbsent",
+        block_on(output.set_low_async()).unwrap_err().to_string()
+    );
+    assert!(!expander.borrow().is_present());
+
+    // No further bus access while absent
+    assert_eq!("DeviceAbsent", output.set_high().unwrap_err().to_string());
+    assert_eq!("DeviceAbsent", input.is_high().unwrap_err().to_string());
+    assert_eq!("DeviceAbsent", block_on(input.is_high_async()).unwrap_err().to_string());
+    assert_eq!(
+        "DeviceAbsent",
+        block_on(input.invert_polarity(true)).unwrap_err().to_string()
+    );
+    assert_eq!("DeviceAbsent", block_on(flex.set_as_output()).unwrap_err().to_string());
+    assert!(matches!(block_on(refreshable), Err(RefreshInputError::DeviceAbsent)));
+    assert!(matches!(
+        block_on(expander.borrow_mut().refresh_input_state(Bank1)),
+        Err(RefreshInputError::DeviceAbsent)
+    ));
+}
+
+#[test]
+fn test_hot_plug_absent_update() {
+    let i2c_bus = BusMockBuilder::new()
+        .mock_write(2)
+        .write_failure(0x03, BusError::Nack)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.enable_hot_plug(1);
+
+    let expander = RefCell::new(expander);
+    let pins = Pins::new(&expander);
+    let mut pin =
+        block_on(pins.get_refreshable_pin(Bank1, Pin0).unwrap().into_output_pin(PinState::High)).unwrap();
+
+    pin.set_low().unwrap();
+    assert_eq!("DeviceAbsent", block_on(pin.update_bank()).unwrap_err().to_string());
+    assert!(!expander.borrow().is_present());
+
+    // No further bus access while absent
+    assert_eq!("DeviceAbsent", block_on(pin.update_bank()).unwrap_err().to_string());
+    assert_eq!("DeviceAbsent", block_on(pin.update_all()).unwrap_err().to_string());
+
+    // Writes of the expander are cached until reattached
+    block_on(expander.borrow_mut().set_state_all(Bank0, false)).unwrap();
+    assert!(expander.borrow().is_dirty());
+}
+
+#[test]
+fn test_hot_plug_absent_refreshable_input() {
+    let i2c_bus = BusMockBuilder::new().read_failure(0x00, BusError::Nack).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.enable_hot_plug(1);
+
+    let expander = RefCell::new(expander);
+    let wait = PollingWait::new(NoDelay {}, 1_000);
+    let pins = Pins::with_wait(&expander, &wait);
+    let mut pin = pins.get_refreshable_pin(Bank0, Pin3).unwrap();
+
+    assert_eq!("DeviceAbsent", block_on(pin.refresh_bank()).unwrap_err().to_string());
+    assert!(!expander.borrow().is_present());
+
+    // Cached input state is outdated while absent
+    assert_eq!("DeviceAbsent", pin.is_high().unwrap_err().to_string());
+    assert_eq!("DeviceAbsent", block_on(pin.is_high_async()).unwrap_err().to_string());
+    assert_eq!("DeviceAbsent", block_on(pin.wait_for_high()).unwrap_err().to_string());
+    assert_eq!(
+        "DeviceAbsent",
+        block_on(pin.wait_for_any_edge()).unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_hot_plug_ignores_other_errors() {
+    let i2c_bus = BusMockBuilder::new().read_error(0x00).into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.enable_hot_plug(1);
+
+    assert!(matches!(
+        block_on(expander.refresh_input_state(Bank0)),
+        Err(RefreshInputError::I2cError(BusError::Error1))
+    ));
+    assert!(expander.is_present());
+}
+
+#[test]
+fn test_hot_plug_reattach() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_write(1, &[0x04, 0b0000_1000])
+        .read_failure(0x00, BusError::Nack)
+        .read_failure(0x04, BusError::Nack)
+        .expect_read(1, 0x04, 0b0000_0000)
+        .expect_read(1, 0x05, 0b0000_0000)
+        .expect_read(1, 0x04, 0b0101_0101)
+        .expect_read(1, 0x05, 0b1010_1010)
+        .expect_read(1, 0x04, 0b1010_1010)
+        .expect_read(1, 0x05, 0b0101_0101)
+        .mock_write(6)
+        .expect_write(1, &[0x02, 0b1111_1111])
+        .expect_write(1, &[0x03, 0b1111_1111])
+        .expect_write(1, &[0x04, 0b0000_1000])
+        .expect_write(1, &[0x06, 0b1111_1111])
+        .expect_write(1, &[0x05, 0b0000_0000])
+        .expect_write(1, &[0x07, 0b1111_1111])
+        .expect_read(1, 0x00, 0b0000_0001)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.enable_hot_plug(1);
+    block_on(expander.reverse_polarity(Bank0, Pin3, true)).unwrap();
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+
+    let expander = Mutex::<NoopRawMutex, _>::new(expander);
+    let hot_plug = HotPlug::<NoopRawMutex, 1>::new();
+    let mut receiver = hot_plug.receiver().unwrap();
+
+    assert_eq!(Presence::Absent, block_on(hot_plug.check(&expander)).unwrap());
+    assert_eq!(Some(Presence::Absent), receiver.try_changed());
+
+    assert_eq!(Presence::Present, block_on(hot_plug.check(&expander)).unwrap());
+    assert_eq!(Some(Presence::Present), receiver.try_changed());
+
+    assert_eq!(Presence::Present, block_on(hot_plug.check(&expander)).unwrap());
+    assert_eq!(None, receiver.try_changed());
+
+    let mut expander = expander.try_lock().unwrap();
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+    assert!(expander.is_pin_input_high(Bank0, Pin0));
+}
+
+#[test]
+fn test_input_filter_majority_vote() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x01, 0b0000_0011)
+        .expect_read(1, 0x01, 0b1000_0001)
+        .expect_read(1, 0x01, 0b0000_0111)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_input_filter(3);
+
+    block_on(expander.refresh_input_state(Bank1)).unwrap();
+    assert_eq!(0b0000_0011, expander.input_state(Bank1));
+    assert_eq!(3, expander.diagnostics().reads);
+}
+
+#[test]
+fn test_input_filter_tie_resolved_low() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0011)
+        .expect_read(1, 0x00, 0b0000_0001)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    expander.set_input_filter(2);
+
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+    assert_eq!(0b0000_0001, expander.input_state(Bank0));
+}
+
+#[test]
+fn test_input_filter_error_keeps_cache() {
+    let i2c_bus = BusMockBuilder::new()
+        .expect_read(1, 0x00, 0b0000_0001)
+        .expect_read(1, 0x00, 0b1111_1111)
+        .read_error(0x00)
+        .into_mock();
+
+    let mut expander = create_expander(i2c_bus);
+    block_on(expander.refresh_input_state(Bank0)).unwrap();
+
+    expander.set_input_filter(3);
+    assert!(block_on(expander.refresh_input_state(Bank0)).is_err());
+    assert_eq!(0b0000_0001, expander.input_state(Bank0));
+}
+
+/// Bus expectations of [assert_lock_backend]
+fn lock_backend_bus() -> MockI2CBus {
+    BusMockBuilder::new()
+        .expect_write(1, &[0x06, 0b1111_1011])
+        .expect_write(1, &[0x02, 0b1111_1011])
+        .expect_write(1, &[0x02, 0b1111_1111])
+        .into_mock()
+}
+
+/// Drives a pin using the given lock and verifies exclusive access
+fn assert_lock_backend<LOCK: ExpanderLock<MockI2CBus, DummyPin>>(expander: &LOCK) {
+    let pins = Pins::new(expander);
+    let mut pin = block_on(pins.get_pin(Bank0, Pin2).unwrap().into_output_pin(PinState::Low)).unwrap();
+
+    pin.set_high().unwrap();
+    assert!(pin.is_set_high().unwrap());
+    assert!(pins.get_pin(Bank0, Pin2).is_none());
+
+    let guard = expander.try_lock().unwrap();
+    assert!(expander.try_lock().is_none());
+    drop(guard);
+    assert!(expander.try_lock().is_some());
 }
 
-/// Testing lock-free RefGuard
-#[cfg(not(feature = "spin"))]
-fn get_pins(expander: &mut PCA9539<MockI2CBus>) -> Pins<MockI2CBus, LockFreeGuard<MockI2CBus>> {
-    expander.pins()
+fn create_expander(i2c_bus: MockI2CBus) -> PCA9539<MockI2CBus, DummyPin> {
+    block_on(PCA9539::new(i2c_bus, 0x74, DummyPin {}))
 }
@@ -0,0 +1,205 @@
+//! # Timeouts
+//!
+//! A hung I2C peripheral would otherwise block all pins forever. Timeouts are applied per expander by wrapping
+//! its bus and its lock:
+//! * [TimeoutBus]: Each bus transaction fails with [TimeoutError::Timeout], if not completed in time
+//! * [TimeoutLock]: Acquiring the expander fails with [RefreshInputError::Timeout](crate::expander::RefreshInputError::Timeout)
+//!   for all asynchronous pin operations. Helpers waiting for the expander, like
+//!   [ExpanderArray](crate::array::ExpanderArray), [HotPlug](crate::hotplug::HotPlug) or
+//!   [Poller](crate::poller::Poller), are failing on timeouts as well.
+//!
+//! The timer is any [DelayNs] implementation, e.g. `embassy_time::Delay` or a user supplied timer.
+//! ```
+//!# use core::cell::RefCell;
+//!# use pca9539::example::{DummyDelay, DummyI2CBus, DummyResetPin};
+//!# use pca9539::expander::Bank::Bank0;
+//!# use pca9539::expander::PCA9539;
+//!# use pca9539::expander::PinID::Pin1;
+//!# use pca9539::pins::Pins;
+//!# use pca9539::timeout::{TimeoutBus, TimeoutLock};
+//!# use pca9539::digital_hal_async::InputPinAsync;
+//!#
+//!# embassy_futures::block_on(async {
+//!# let i2c_bus = DummyI2CBus::default();
+//! // Bus transactions time out after 10 ms
+//! let i2c_bus = TimeoutBus::new(i2c_bus, DummyDelay::default(), 10_000);
+//! let expander = PCA9539::new(i2c_bus, 0x74, DummyResetPin::default()).await;
+//!
+//! // Waiting for the expander times out after 50 ms
+//! let expander = TimeoutLock::new(RefCell::new(expander), DummyDelay::default(), 50_000);
+//! let pins = Pins::new(&expander);
+//!
+//! let mut pin01 = pins.get_pin(Bank0, Pin1).unwrap();
+//! assert!(pin01.is_high_async().await.unwrap());
+//!# });
+//! ```
+
+use crate::lock::ExpanderLock;
+use embassy_futures::select::{select, Either};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::{ErrorKind, ErrorType, Operation, SevenBitAddress};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+/// Operation did not complete in time
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Timeout {}
+
+/// Error of [TimeoutBus]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TimeoutError<E> {
+    /// Error of the wrapped bus
+    Bus(E),
+    /// Transaction did not complete in time
+    Timeout,
+}
+
+impl<E: embedded_hal::i2c::Error> embedded_hal::i2c::Error for TimeoutError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            TimeoutError::Bus(error) => error.kind(),
+            TimeoutError::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+/// Wraps a bus, failing each transaction which does not complete within the configured timeout
+pub struct TimeoutBus<B, DELAY>
+where
+    B: I2c,
+    DELAY: DelayNs,
+{
+    bus: B,
+    delay: DELAY,
+
+    /// Timeout of each transaction in microseconds
+    timeout_us: u32,
+}
+
+impl<B: I2c, DELAY: DelayNs> TimeoutBus<B, DELAY> {
+    /// Creates a new bus with the given timeout in microseconds
+    pub fn new(bus: B, delay: DELAY, timeout_us: u32) -> Self {
+        Self { bus, delay, timeout_us }
+    }
+
+    /// Returns the wrapped bus
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+impl<B: I2c, DELAY: DelayNs> ErrorType for TimeoutBus<B, DELAY> {
+    type Error = TimeoutError<B::Error>;
+}
+
+impl<B: I2c, DELAY: DelayNs> I2c<SevenBitAddress> for TimeoutBus<B, DELAY> {
+    async fn read(&mut self, address: SevenBitAddress, read: &mut [u8]) -> Result<(), Self::Error> {
+        with_timeout(self.bus.read(address, read), &mut self.delay, self.timeout_us).await
+    }
+
+    async fn write(&mut self, address: SevenBitAddress, write: &[u8]) -> Result<(), Self::Error> {
+        with_timeout(self.bus.write(address, write), &mut self.delay, self.timeout_us).await
+    }
+
+    async fn write_read(
+        &mut self,
+        address: SevenBitAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        with_timeout(
+            self.bus.write_read(address, write, read),
+            &mut self.delay,
+            self.timeout_us,
+        )
+        .await
+    }
+
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        with_timeout(
+            self.bus.transaction(address, operations),
+            &mut self.delay,
+            self.timeout_us,
+        )
+        .await
+    }
+}
+
+/// Runs the given bus transaction, failing if not completed in time
+async fn with_timeout<E, F, DELAY>(
+    transaction: F,
+    delay: &mut DELAY,
+    timeout_us: u32,
+) -> Result<(), TimeoutError<E>>
+where
+    F: core::future::Future<Output = Result<(), E>>,
+    DELAY: DelayNs,
+{
+    match select(transaction, delay.delay_us(timeout_us)).await {
+        Either::First(result) => result.map_err(TimeoutError::Bus),
+        Either::Second(_) => Err(TimeoutError::Timeout),
+    }
+}
+
+/// Wraps a lock, failing the acquisition if the expander is not available within the configured timeout
+pub struct TimeoutLock<LOCK, DELAY>
+where
+    DELAY: DelayNs + Clone,
+{
+    lock: LOCK,
+    delay: DELAY,
+
+    /// Timeout of each acquisition in microseconds
+    timeout_us: u32,
+}
+
+impl<LOCK, DELAY: DelayNs + Clone> TimeoutLock<LOCK, DELAY> {
+    /// Creates a new lock with the given timeout in microseconds
+    pub fn new(lock: LOCK, delay: DELAY, timeout_us: u32) -> Self {
+        Self {
+            lock,
+            delay,
+            timeout_us,
+        }
+    }
+
+    /// Returns the wrapped lock
+    pub fn into_inner(self) -> LOCK {
+        self.lock
+    }
+}
+
+impl<I2CT, RESET, LOCK, DELAY> ExpanderLock<I2CT, RESET> for TimeoutLock<LOCK, DELAY>
+where
+    I2CT: I2c,
+    RESET: OutputPin,
+    LOCK: ExpanderLock<I2CT, RESET>,
+    DELAY: DelayNs + Clone,
+{
+    type RawMutex = LOCK::RawMutex;
+    type RetryDelay = LOCK::RetryDelay;
+
+    type Guard<'a>
+        = LOCK::Guard<'a>
+    where
+        Self: 'a;
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.lock.try_lock()
+    }
+
+    async fn lock(&self) -> Self::Guard<'_> {
+        self.lock.lock().await
+    }
+
+    async fn lock_timeout(&self) -> Result<Self::Guard<'_>, Timeout> {
+        match select(self.lock.lock(), self.delay.clone().delay_us(self.timeout_us)).await {
+            Either::First(guard) => Ok(guard),
+            Either::Second(_) => Err(Timeout {}),
+        }
+    }
+}
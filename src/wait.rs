@@ -0,0 +1,68 @@
+//! # Wait strategies
+//!
+//! Input pins implement [Wait] by re-evaluating the input state
+//! each time the configured strategy signals a possible change.
+//! See [waiting section](crate::pins#waiting-for-input-changes) for more details.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+/// Defines how input pins are waiting for possible input changes
+pub trait WaitStrategy {
+    /// Resolves as soon as the input state may have changed
+    async fn wait_for_change(&self);
+}
+
+/// Default strategy of pins, which does not support waiting at all
+pub struct NoWait {}
+
+/// Waits on the INT output of PCA9539, which is pulled low as long as any input differs from the
+/// last read input register state.
+pub struct InterruptWait<RAWMUTEX, INT>
+where
+    RAWMUTEX: RawMutex,
+    INT: Wait,
+{
+    int: Mutex<RAWMUTEX, INT>,
+}
+
+impl<RAWMUTEX: RawMutex, INT: Wait> InterruptWait<RAWMUTEX, INT> {
+    /// Creates a new strategy based on the MCU pin connected to INT
+    pub fn new(int: INT) -> Self {
+        Self { int: Mutex::new(int) }
+    }
+}
+
+impl<RAWMUTEX: RawMutex, INT: Wait> WaitStrategy for InterruptWait<RAWMUTEX, INT> {
+    async fn wait_for_change(&self) {
+        // Errors of the INT pin are treated as possible change, so the input state gets re-evaluated
+        let _ = self.int.lock().await.wait_for_low().await;
+    }
+}
+
+/// Polling fallback for boards without routed INT line. The input state is re-evaluated after
+/// the configured interval.
+pub struct PollingWait<DELAY>
+where
+    DELAY: DelayNs + Clone,
+{
+    delay: DELAY,
+
+    /// Polling interval in microseconds
+    interval_us: u32,
+}
+
+impl<DELAY: DelayNs + Clone> PollingWait<DELAY> {
+    /// Creates a new polling strategy with the given interval in microseconds
+    pub fn new(delay: DELAY, interval_us: u32) -> Self {
+        Self { delay, interval_us }
+    }
+}
+
+impl<DELAY: DelayNs + Clone> WaitStrategy for PollingWait<DELAY> {
+    async fn wait_for_change(&self) {
+        self.delay.clone().delay_us(self.interval_us).await;
+    }
+}